@@ -22,7 +22,7 @@ pub fn main() {
     let src_dir = Path::new(&var("CARGO_MANIFEST_DIR").unwrap()).join(DIR);
     let dest_dir = Path::new(&var("CARGO_MANIFEST_DIR").unwrap()).join("target").join(var("PROFILE").unwrap()).join(DIR);
 
-    if let Err(_) = copy_dir_all(src_dir, dest_dir) {
+    if copy_dir_all(src_dir, dest_dir).is_err() {
         println!("cargo:warning=failed to copy {}", DIR);
     }
 }
\ No newline at end of file
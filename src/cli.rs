@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+
+pub struct Args {
+    // extra import search paths, searched before all others
+    pub paths: Vec<PathBuf>,
+    // whether to import the embedded stdlib on start up
+    pub stdlib: bool,
+    // a project manifest, or a directory containing one, to load instead of the one in the current directory
+    pub project: Option<PathBuf>,
+    // whether to open the interpreter after running a project's main function
+    pub interactive: bool,
+    // code to show the expansion of instead of running anything
+    pub expand: Option<String>,
+}
+
+pub const USAGE: &str = "usage: stack_sharp [options]
+options:
+    -I, --path <dir>    search <dir> for imports before STACK_SHARP_PATH and ss_src (can be repeated)
+    --no-stdlib         start with an empty environment instead of importing the stdlib
+    --project <path>    load the project manifest at <path>, or in it if it is a directory (default: ./stack_sharp.toml)
+    -i, --interactive   open the interpreter after running the project's main function instead of exiting
+    -e, --expand <code> show the words <code> expands to and the codes it compiles to, then exit
+    -h, --help          show this message";
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args { paths: Vec::new(), stdlib: true, project: None, interactive: false, expand: None };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-I"|"--path" => match args.next() {
+                Some(path) => parsed.paths.push(PathBuf::from(path)),
+                None => return Err(format!("{} requires a directory", arg)),
+            }
+            "--no-stdlib" => parsed.stdlib = false,
+            "--project" => match args.next() {
+                Some(path) => parsed.project = Some(PathBuf::from(path)),
+                None => return Err(format!("{} requires a path", arg)),
+            }
+            "-i"|"--interactive" => parsed.interactive = true,
+            "-e"|"--expand" => match args.next() {
+                Some(code) => parsed.expand = Some(code),
+                None => return Err(format!("{} requires code", arg)),
+            }
+            "-h"|"--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => {
+                if let Some(path) = arg.strip_prefix("-I") {
+                    parsed.paths.push(PathBuf::from(path));
+                } else {
+                    return Err(format!("unknown argument {}", arg));
+                }
+            }
+        }
+    }
+    Ok(parsed)
+}
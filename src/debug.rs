@@ -1,24 +1,32 @@
 use std::collections::HashMap;
 
-use super::interpret::{HEAP_START, MEMORY_SIZE};
+use super::interpret::{Cell, Interpreter, Value};
 
-pub fn free_heap(heap: &[i64], heap_free_pointer: &usize) -> HashMap<usize, u8> {
-    let mut free_heap: HashMap<usize, u8> = HashMap::new();
+// (start, length) of each block in the free heap list, in list order
+pub fn free_blocks(memory: &[Cell], heap_start: usize, heap_free_pointer: &usize) -> Vec<(usize, usize)> {
+    let mut blocks: Vec<(usize, usize)> = Vec::new();
     let mut pointer: usize = *heap_free_pointer;
-    let mut num: u8 = 0;
-    while pointer != 0 {
-        free_heap.extend((pointer .. pointer + heap[pointer + 1] as usize).map(|x| (x, num)));
-        pointer = heap[pointer] as usize;
-        num += 1;
+    // a corrupted list could loop forever or point outside of the heap
+    while pointer != 0 && (heap_start..memory.len() - 1).contains(&pointer) && blocks.len() < memory.len() {
+        blocks.push((pointer, memory[pointer + 1].value().max(0) as usize));
+        pointer = memory[pointer].value() as usize;
+    }
+    blocks
+}
+
+pub fn free_heap(heap: &[Cell], heap_start: usize, heap_free_pointer: &usize) -> HashMap<usize, u8> {
+    let mut free_heap: HashMap<usize, u8> = HashMap::new();
+    for (num, (pointer, length)) in free_blocks(heap, heap_start, heap_free_pointer).into_iter().enumerate() {
+        free_heap.extend((pointer .. pointer + length).map(|x| (x, num as u8)));
     }
-    return free_heap;
+    free_heap
 }
 
-pub fn print_heap(memory: &[i64; MEMORY_SIZE], heap_pointer: &usize, heap_free_pointer: &usize) {
-    let freeheap = free_heap(&memory[..], heap_free_pointer);
+pub fn print_heap(memory: &[Cell], heap_start: usize, heap_pointer: &usize, heap_free_pointer: &usize) {
+    let freeheap = free_heap(memory, heap_start, heap_free_pointer);
     let mut heap: String = String::new();
-    for (i, int) in memory[HEAP_START..std::cmp::min(*heap_pointer, HEAP_START + 20)].iter().enumerate() {
-        if let Some(num) = freeheap.get(&(i + HEAP_START)) {
+    for (i, int) in memory[heap_start..std::cmp::min(*heap_pointer, heap_start + 20)].iter().enumerate() {
+        if let Some(num) = freeheap.get(&(i + heap_start)) {
             heap.push_str(&format!("{}({}), ", int, num));
         } else {
             heap.push_str(&format!("{}, ", int));
@@ -26,4 +34,325 @@ pub fn print_heap(memory: &[i64; MEMORY_SIZE], heap_pointer: &usize, heap_free_p
     }
     heap.truncate(heap.len().saturating_sub(2));
     println!("heap: [{}] | end: {}, free: {}", heap, heap_pointer, heap_free_pointer);
+}
+
+//: heap inspector
+pub fn inspect_heap<'a>(interpreter: &Interpreter, mut args: impl Iterator<Item = &'a str>) {
+    let parse_addr = |arg: Option<&str>| -> Option<usize> {
+        match arg.map(str::parse::<usize>) {
+            Some(Ok(addr)) if addr < interpreter.memory.len() => Some(addr),
+            Some(_) => {
+                super::print_err(format!("invalid address: {}", arg.unwrap_or_default()));
+                None
+            }
+            None => {
+                super::print_err("expected an address");
+                None
+            }
+        }
+    };
+    match args.next() {
+        Some("blocks") => print_heap_blocks(interpreter),
+        Some("free") => print_free_list(interpreter),
+        Some(kind @ ("obj"|"list"|"array")) => {
+            if let Some(addr) = parse_addr(args.next()) {
+                let mut decoded = String::new();
+                match kind {
+                    "obj" => decode_obj(&interpreter.memory, addr, 0, &mut decoded),
+                    "list" => decode_list(&interpreter.memory, addr, &mut decoded),
+                    _ => decode_array(&interpreter.memory, addr, &mut decoded),
+                }
+                println!("{}: {}", addr, decoded);
+            }
+        }
+        arg => {
+            if let Some(start) = parse_addr(arg) {
+                let end = match args.next() {
+                    Some(end) => match parse_addr(Some(end)) {
+                        Some(end) => end,
+                        None => return,
+                    },
+                    None => start,
+                };
+                if start > end {
+                    super::print_err(format!("invalid range: the start ({}) is after the end ({})", start, end));
+                } else {
+                    print_memory_range(interpreter, start, end);
+                }
+            }
+        }
+    }
+}
+
+fn print_memory_range(interpreter: &Interpreter, start: usize, end: usize) {
+    let freeheap = free_heap(&interpreter.memory, interpreter.heap_start, &interpreter.heap_free_pointer);
+    for addr in start..=end {
+        let mut line = format!("{:>4}: {}", addr, interpreter.memory[addr]);
+        if let Some(num) = freeheap.get(&addr) {
+            line.push_str(&format!(" (free {})", num));
+        } else if let Some((block_start, length)) = interpreter.heap_blocks.range(..=addr).next_back() {
+            if addr < block_start + length {
+                line.push_str(&format!(" (block {}+{})", block_start, addr - block_start));
+            }
+        }
+        println!("{}", line);
+    }
+}
+
+fn print_heap_blocks(interpreter: &Interpreter) {
+    for (start, length) in &interpreter.heap_blocks {
+        let cells: Vec<String> = interpreter.memory[*start..start + length].iter().map(Cell::to_string).collect();
+        println!("{:>4}: len {} [{}]", start, length, cells.join(", "));
+    }
+    let used: usize = interpreter.heap_blocks.values().sum();
+    println!("blocks: {} | used: {} / {} cells", interpreter.heap_blocks.len(), used, interpreter.memory.len() - interpreter.heap_start);
+}
+
+fn print_free_list(interpreter: &Interpreter) {
+    let blocks = free_blocks(&interpreter.memory, interpreter.heap_start, &interpreter.heap_free_pointer);
+    for (num, (start, length)) in blocks.iter().enumerate() {
+        println!("({}) {:>4}: len {} -> {}", num, start, length, interpreter.memory[*start]);
+    }
+    let tail = interpreter.memory.len() - interpreter.heap_pointer;
+    let listed: usize = blocks.iter().map(|(_, length)| length).sum();
+    let largest = blocks.iter().map(|(_, length)| *length).chain(std::iter::once(tail)).max().unwrap_or(0);
+    let total = listed + tail;
+    // the proportion of free cells that cannot be used for a single allocation
+    let fragmentation = if total == 0 { 0.0 } else { 100.0 * (total - largest) as f64 / total as f64 };
+    println!("free blocks: {} ({} cells) | after end: {} | largest: {} | fragmentation: {:.1}%",
+        blocks.len(), listed, tail, largest, fragmentation);
+}
+//;
+
+//: stdlib object decoding
+const TYPE_OBJ: Value = 0;
+const TYPE_NUM: Value = 1;
+const TYPE_ARRAY: Value = 2;
+const TYPE_LIST: Value = 3;
+
+fn cell(memory: &[Cell], addr: Value) -> Option<Value> {
+    if addr > 0 && (addr as usize) < memory.len() {
+        Some(memory[addr as usize].value())
+    } else {
+        None
+    }
+}
+
+// *obj layout: [pointer/value, type]
+fn decode_obj(memory: &[Cell], addr: usize, depth: usize, out: &mut String) {
+    if addr + 1 >= memory.len() {
+        out.push_str("<out of memory>");
+        return;
+    }
+    match memory[addr + 1].value() {
+        TYPE_OBJ if depth < 16 => {
+            out.push_str("obj -> ");
+            match cell(memory, memory[addr].value()) {
+                Some(_) => decode_obj(memory, memory[addr].value() as usize, depth + 1, out),
+                None => out.push_str(&format!("<invalid pointer {}>", memory[addr])),
+            }
+        }
+        TYPE_OBJ => out.push_str("obj -> ..."),
+        TYPE_NUM => out.push_str(&format!("num {}", memory[addr])),
+        TYPE_ARRAY => {
+            out.push_str("array ");
+            match cell(memory, memory[addr].value()) {
+                Some(_) => decode_array(memory, memory[addr].value() as usize, out),
+                None => out.push_str(&format!("<invalid pointer {}>", memory[addr])),
+            }
+        }
+        TYPE_LIST => {
+            out.push_str("list ");
+            decode_list(memory, addr, out);
+        }
+        -1 => out.push_str("undefined"),
+        other => out.push_str(&format!("<unknown type {}>", other)),
+    }
+}
+
+// array layout: [length, elements..]
+fn decode_array(memory: &[Cell], addr: usize, out: &mut String) {
+    let length = memory[addr].value();
+    if length < 0 || length as usize >= memory.len() - addr {
+        out.push_str(&format!("<invalid length {}>", length));
+        return;
+    }
+    let elements: Vec<String> = memory[addr + 1..=addr + length as usize].iter().map(Cell::to_string).collect();
+    out.push_str(&format!("[{}] ({})", elements.join(", "), length));
+}
+
+// list_var layout: [pointer to first item], item layout: [pointer to next item, value]
+fn decode_list(memory: &[Cell], addr: usize, out: &mut String) {
+    let mut elements: Vec<String> = Vec::new();
+    let mut pointer = memory[addr].value();
+    while pointer != 0 {
+        if elements.len() >= memory.len() {
+            elements.push("...".to_owned());
+            break;
+        }
+        // the item's value is after its pointer, which cannot be the last cell
+        let value = cell(memory, pointer).and(pointer.checked_add(1)).filter(|value| cell(memory, *value).is_some());
+        match (cell(memory, pointer), value) {
+            (Some(next), Some(value)) => {
+                elements.push(memory[value as usize].to_string());
+                pointer = next;
+            }
+            _ => {
+                elements.push(format!("<invalid pointer {}>", pointer));
+                break;
+            }
+        }
+    }
+    out.push_str(&format!("[{}] ({})", elements.join(", "), elements.len()));
+}
+//;
+
+//: variables
+pub fn print_vars(interpreter: &Interpreter) {
+    for (name, addr) in interpreter.named_variables() {
+        let watched = if interpreter.watchpoints.contains(&addr) { " (watched)" } else { "" };
+        println!("{:>4}: {} = {}{}", addr, name, interpreter.memory[addr], watched);
+    }
+}
+
+fn address_name(interpreter: &Interpreter, addr: usize) -> String {
+    let names: Vec<&str> = interpreter.named_variables().into_iter()
+        .filter(|(_, var_addr)| *var_addr == addr)
+        .map(|(name, _)| name)
+        .collect();
+    if names.is_empty() {
+        addr.to_string()
+    } else {
+        format!("{} ({})", names.join("|"), addr)
+    }
+}
+
+// toggles a watchpoint on each address or variable name, listing the watchpoints if none are given
+pub fn watch<'a>(interpreter: &mut Interpreter, args: impl Iterator<Item = &'a str>) {
+    let mut any = false;
+    for arg in args {
+        any = true;
+        let addr = match arg.parse::<usize>() {
+            Ok(addr) if addr > 0 && addr < interpreter.memory.len() => addr,
+            Ok(_) => {
+                super::print_err(format!("cannot watch address {}", arg));
+                continue;
+            }
+            Err(_) => match interpreter.variable_address(arg) {
+                Some(addr) => addr,
+                None => {
+                    super::print_err(format!("no variable named {}", arg));
+                    continue;
+                }
+            }
+        };
+        if interpreter.watchpoints.remove(&addr) {
+            println!("unwatched {}", address_name(interpreter, addr));
+        } else {
+            interpreter.watchpoints.insert(addr);
+            println!("watching {}", address_name(interpreter, addr));
+        }
+    }
+    if !any {
+        let mut watchpoints: Vec<&usize> = interpreter.watchpoints.iter().collect();
+        watchpoints.sort();
+        for addr in watchpoints {
+            println!("{} = {}", address_name(interpreter, *addr), interpreter.memory[*addr]);
+        }
+    }
+}
+
+pub fn print_watch_hit(interpreter: &Interpreter, addr: usize, previous: Cell) {
+    println!("watchpoint {}: {} -> {} (paused)", address_name(interpreter, addr), previous, interpreter.memory[addr]);
+}
+//;
+
+//: macro expansion
+// shows the words some code expands to next to the codes each compiles to, without keeping anything it compiles or defines
+pub fn print_expansion(interpreter: &mut Interpreter, source: &str, compiler_optimise: bool) {
+    let (expansion, codes) = interpreter.expand(source, compiler_optimise);
+    // each code is shown next to the last word that compiled to it
+    let mut owners: Vec<Option<usize>> = vec![None; codes.len()];
+    for (i, word) in expansion.iter().enumerate() {
+        for owner in &mut owners[word.codes.start.min(codes.len())..word.codes.end.min(codes.len())] {
+            *owner = Some(i);
+        }
+    }
+    let rows: Vec<(String, Vec<String>)> = expansion.iter().enumerate()
+        .map(|(i, word)| (
+            format!("{}{}", "  ".repeat(word.depth), word.word),
+            owners.iter().enumerate()
+                .filter(|(_, owner)| **owner == Some(i))
+                .map(|(loc, _)| format!("{:>4}: {:?}", loc, codes[loc]))
+                .collect(),
+        ))
+        .collect();
+    let width = rows.iter().map(|(word, _)| word.chars().count()).chain(std::iter::once("words".len())).max().unwrap_or(0);
+    println!("{:<width$} | codes", "words");
+    for (word, codes) in rows {
+        let mut codes = codes.into_iter();
+        println!("{}", format!("{:<width$} | {}", word, codes.next().unwrap_or_default()).trim_end());
+        for code in codes {
+            println!("{:<width$} | {}", "", code);
+        }
+    }
+}
+//;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory(cells: &[Value]) -> Vec<Cell> {
+        cells.iter().map(|cell| Cell::Int(*cell)).collect()
+    }
+
+    fn list(memory: &[Cell], addr: usize) -> String {
+        let mut out = String::new();
+        decode_list(memory, addr, &mut out);
+        out
+    }
+
+    fn array(memory: &[Cell], addr: usize) -> String {
+        let mut out = String::new();
+        decode_array(memory, addr, &mut out);
+        out
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(list(&memory(&[0, 2, 4, 5, 0, 7]), 1), "[5, 7] (2)");
+        assert_eq!(list(&memory(&[0, 0]), 1), "[] (0)");
+        // a big value is shown in full
+        let mut big = memory(&[0, 2, 0, 0]);
+        big[3] = Cell::from(num_bigint::BigInt::from(Value::MAX) * 4);
+        assert_eq!(list(&big, 1), "[36893488147419103228] (1)");
+    }
+
+    #[test]
+    fn corrupt_lists() {
+        assert_eq!(list(&memory(&[0, -3]), 1), "[<invalid pointer -3>] (1)");
+        assert_eq!(list(&memory(&[0, 9]), 1), "[<invalid pointer 9>] (1)");
+        // the item's value would be past the end of memory
+        assert_eq!(list(&memory(&[0, 2, 0]), 1), "[<invalid pointer 2>] (1)");
+        assert_eq!(list(&memory(&[0, Value::MAX]), 1), format!("[<invalid pointer {}>] (1)", Value::MAX));
+        // a list that loops forever is cut off
+        assert_eq!(list(&memory(&[0, 2, 2, 1]), 1), "[1, 1, 1, 1, ...] (5)");
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(array(&memory(&[0, 2, 5, 7]), 1), "[5, 7] (2)");
+        assert_eq!(array(&memory(&[0, 0]), 1), "[] (0)");
+    }
+
+    #[test]
+    fn corrupt_arrays() {
+        assert_eq!(array(&memory(&[0, -1]), 1), "<invalid length -1>");
+        assert_eq!(array(&memory(&[0, 3, 5, 7]), 1), "<invalid length 3>");
+        assert_eq!(array(&memory(&[0, Value::MAX]), 1), format!("<invalid length {}>", Value::MAX));
+        let mut big = memory(&[0, 0]);
+        big[1] = Cell::from(num_bigint::BigInt::from(Value::MAX) * 4);
+        assert_eq!(array(&big, 1), format!("<invalid length {}>", Value::MAX));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,550 @@
+use std::collections::{HashMap, HashSet};
+
+use super::interpret::{FloatOpCode, OpCode, ProgramCode, Value, Variable};
+
+//: types
+// what a number on the stack is used as
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum Type {
+    Int,
+    Bool,
+    Char,
+    // the address of a cell in memory
+    Ptr,
+    // the address of a variable holding the pointer to the first item of a list
+    ListVar,
+    // the address of an array's length, which its items follow
+    ArrayPtr,
+}
+impl Type {
+    pub const NAMES: &'static str = "int, bool, char, ptr, list-var or array-ptr";
+
+    pub fn parse(name: &str) -> Option<Type> {
+        match name {
+            "int" => Some(Type::Int),
+            "bool" => Some(Type::Bool),
+            "char" => Some(Type::Char),
+            "ptr" => Some(Type::Ptr),
+            "list-var" => Some(Type::ListVar),
+            "array-ptr" => Some(Type::ArrayPtr),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Type::Int => "int",
+            Type::Bool => "bool",
+            Type::Char => "char",
+            Type::Ptr => "ptr",
+            Type::ListVar => "list-var",
+            Type::ArrayPtr => "array-ptr",
+        }
+    }
+
+    fn is_pointer(self) -> bool {
+        matches!(self, Type::Ptr | Type::ListVar | Type::ArrayPtr)
+    }
+
+    // whether a number of this type can be used where one of the expected type is, where numbers can be used as each other and
+    // a plain pointer as any kind of pointer, but never a number as a pointer, a pointer as a number or a list as an array
+    fn fits(self, expected: Type) -> bool {
+        self == expected || self.is_pointer() == expected.is_pointer() && (!self.is_pointer() || self == Type::Ptr || expected == Type::Ptr)
+    }
+}
+
+// the name of an item in an annotation, eg. `list` in `list:list-var`, and its type, or None if it can be anything
+#[derive(Clone, Eq, PartialEq)]
+pub struct Item {
+    pub name: String,
+    pub kind: Option<Type>,
+}
+impl Item {
+    fn parse(item: &str) -> Result<Item, String> {
+        match item.split_once(':') {
+            Some((name, kind)) => match Type::parse(kind) {
+                Some(kind) => Ok(Item { name: name.to_owned(), kind: Some(kind) }),
+                None => Err(format!("unknown type {} (expected {})", kind, Type::NAMES)),
+            },
+            None => Ok(Item { name: item.to_owned(), kind: None }),
+        }
+    }
+}
+//;
+
+// the items a function takes from the top of the stack and the items it leaves in their place, each from the deepest up
+#[derive(Clone, Eq, PartialEq)]
+pub struct StackEffect {
+    pub inputs: Vec<Item>,
+    pub outputs: Vec<Item>,
+}
+impl StackEffect {
+    // parses the inside of a `/*: a b -- c */` annotation, where each word before the `--` is an input and each after it an output,
+    // which can be given a type, eg. `value list:list-var --`
+    pub fn parse(annotation: &str) -> Result<StackEffect, String> {
+        let Some((inputs, outputs)) = annotation.split_once("--").filter(|(_, outputs)| !outputs.contains("--")) else {
+            return Err("expected eg. `/*: a b -- c */`".to_owned());
+        };
+        Ok(StackEffect {
+            inputs: inputs.split_whitespace().map(Item::parse).collect::<Result<_, _>>()?,
+            outputs: outputs.split_whitespace().map(Item::parse).collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+// the annotations the checker uses
+#[derive(Default, Clone)]
+pub struct Annotations {
+    // the stack effect of each annotated function, by its location
+    pub effects: HashMap<usize, StackEffect>,
+    // the type of each variable that has been given one with `:name/*: type */`, by its address
+    pub variables: HashMap<usize, Type>,
+    // the address of the variable that each number compiled from a variable's name is, by its location, for the functions not yet checked
+    pub addresses: HashMap<usize, usize>,
+}
+impl Annotations {
+    // moves the annotations of the code that is kept when compacting, dropping the rest
+    pub fn relocate(&mut self, keep: &[bool], relocate: impl Fn(usize) -> usize) {
+        self.effects = std::mem::take(&mut self.effects).into_iter()
+            .filter(|(loc, _)| keep[*loc])
+            .map(|(loc, effect)| (relocate(loc), effect))
+            .collect();
+        self.addresses = std::mem::take(&mut self.addresses).into_iter()
+            .filter(|(loc, _)| keep[*loc])
+            .map(|(loc, addr)| (relocate(loc), addr))
+            .collect();
+    }
+}
+
+//: abstract interpretation
+enum Failure {
+    // the number of items depends on something only known at run time, eg. a late bound call, so nothing can be said
+    Unknown,
+    Mismatch(String),
+}
+
+// the stack, relative to its depth where the function started
+#[derive(Clone)]
+struct State {
+    depth: Value,
+    // the lowest depth reached, which is minus the number of inputs used
+    lowest: Value,
+    // the types of as many of the top items as are known, from the deepest up, where None is an item that could be anything
+    types: Vec<Option<Type>>,
+    // the top item, if it is a constant
+    top: Option<Value>,
+}
+
+// the state at the end of some code, or None if it never gets there, eg. because it always loops back with `}` or ends with `!?`
+type Flow = Result<Option<State>, Failure>;
+
+// what is known while checking functions
+struct Context<'a> {
+    codes: &'a [ProgramCode],
+    annotations: &'a Annotations,
+    variables: &'a HashMap<String, Variable>,
+    // the effects of functions without annotations, worked out from their code when they are first called
+    inferred: HashMap<usize, Option<StackEffect>>,
+    // functions being inferred, so that recursion without an annotation is not followed forever
+    inferring: HashSet<usize>,
+}
+
+struct Checker<'a, 'b> {
+    context: &'b mut Context<'a>,
+    // the number of inputs the function may take, or None when working out how many it takes
+    inputs: Option<usize>,
+    // the depth at the start of each loop
+    loops: HashMap<usize, Value>,
+}
+
+impl Context<'_> {
+    fn name_of(&self, loc: usize) -> String {
+        super::parse::unmangle(self.variables.iter()
+            .filter(|(_, var)| **var == Variable::Function(loc))
+            .map(|(name, _)| name.as_str())
+            .min()
+            .unwrap_or_default())
+    }
+
+    fn effect_of(&mut self, loc: usize) -> Result<StackEffect, Failure> {
+        if let Some(effect) = self.annotations.effects.get(&loc) {
+            return Ok(effect.clone());
+        }
+        if let Some(effect) = self.inferred.get(&loc) {
+            return effect.clone().ok_or(Failure::Unknown);
+        }
+        let Some(ProgramCode::FuncStart(Some(end))) = self.codes.get(loc) else {
+            return Err(Failure::Unknown);
+        };
+        if !self.inferring.insert(loc) {
+            return Err(Failure::Unknown);
+        }
+        let start = State { depth: 0, lowest: 0, types: Vec::new(), top: None };
+        let flow = Checker { context: self, inputs: None, loops: HashMap::new() }.run(loc + 1, end.get(), start);
+        self.inferring.remove(&loc);
+        let effect = match flow {
+            Ok(Some(state)) => {
+                let outputs = (state.depth - state.lowest) as usize;
+                // the types of the items it leaves are known if it pushed them itself
+                let known = state.types.len().min(outputs);
+                let types = std::iter::repeat_n(None, outputs - known).chain(state.types[state.types.len() - known..].iter().copied());
+                Some(StackEffect {
+                    inputs: vec![Item { name: String::new(), kind: None }; -state.lowest as usize],
+                    outputs: types.map(|kind| Item { name: String::new(), kind }).collect(),
+                })
+            }
+            _ => None,
+        };
+        self.inferred.insert(loc, effect.clone());
+        effect.ok_or(Failure::Unknown)
+    }
+}
+
+// the type of the result of an operator on numbers of the types given
+fn op_type(op: OpCode, left: Option<Type>, right: Option<Type>) -> Option<Type> {
+    use OpCode::*;
+    let pointer = |kind: Option<Type>| kind.is_some_and(Type::is_pointer);
+    Some(match op {
+        LT | GT | LE | GE | EQ | NE => Type::Bool,
+        NOT if right == Some(Type::Bool) => Type::Bool,
+        AND | OR | XOR if left == Some(Type::Bool) && right == Some(Type::Bool) => Type::Bool,
+        // an item that could be anything could be a pointer
+        ADD | SUB if left.is_none() || right.is_none() => return None,
+        ADD | SUB if pointer(left) && !pointer(right) || op == ADD && pointer(right) && !pointer(left) => Type::Ptr,
+        ADD | SUB if left == Some(Type::Char) && !pointer(right) || op == ADD && right == Some(Type::Char) && !pointer(left) => Type::Char,
+        _ => Type::Int,
+    })
+}
+
+impl Checker<'_, '_> {
+    // takes count items, returning their types from the deepest up
+    fn take(&self, state: &mut State, count: Value) -> Result<Vec<Option<Type>>, Failure> {
+        state.depth = state.depth.saturating_sub(count);
+        state.lowest = state.lowest.min(state.depth);
+        state.top = None;
+        if let Some(inputs) = self.inputs {
+            if state.depth < -(inputs as Value) {
+                return Result::Err(Failure::Mismatch(format!("it takes more than its {} inputs", inputs)));
+            }
+        }
+        let count = count.clamp(0, Value::from(u16::MAX)) as usize;
+        let known = state.types.split_off(state.types.len().saturating_sub(count));
+        Ok(std::iter::repeat_n(None, count - known.len()).chain(known).collect())
+    }
+
+    fn give(&self, state: &mut State, types: impl IntoIterator<Item = Option<Type>>) {
+        for kind in types {
+            state.depth = state.depth.saturating_add(1);
+            state.types.push(kind);
+        }
+        state.top = None;
+    }
+
+    // the state after each code from start until end, following if statements and calls
+    fn run(&mut self, start: usize, end: usize, mut state: State) -> Flow {
+        use ProgramCode::*;
+        let mut i = start;
+        while i < end {
+            let top = state.top;
+            match self.context.codes[i] {
+                Int(value) => {
+                    let variable = self.context.annotations.addresses.get(&i).filter(|addr| **addr as Value == value);
+                    let kind = match variable {
+                        Some(addr) => self.context.annotations.variables.get(addr).copied().unwrap_or(Type::Ptr),
+                        None => Type::Int,
+                    };
+                    self.give(&mut state, [Some(kind)]);
+                    state.top = Some(value);
+                }
+                FuncCall(loc) => {
+                    let effect = self.context.effect_of(loc)?;
+                    let given = self.take(&mut state, effect.inputs.len() as Value)?;
+                    for (input, given) in effect.inputs.iter().zip(given) {
+                        if let (Some(expected), Some(given)) = (input.kind, given) {
+                            if !given.fits(expected) {
+                                return Result::Err(Failure::Mismatch(format!("{} takes {} as {} but is given {}",
+                                    self.context.name_of(loc), input.name, expected.name(), given.name())));
+                            }
+                        }
+                    }
+                    self.give(&mut state, effect.outputs.iter().map(|output| output.kind));
+                }
+                // a function defined inside another does not run where it is defined
+                FuncStart(Some(end)) => {
+                    i = end.get() + 1;
+                    continue;
+                }
+                PopMemLoc | PopMem => _ = self.take(&mut state, 1)?,
+                PushMem => self.give(&mut state, [None]),
+                Err => return Ok(None),
+                If(Some(target)) => {
+                    let (flow, next) = self.if_statement(i, target.get(), state)?;
+                    match flow {
+                        Some(flow) => state = flow,
+                        None => return Ok(None),
+                    }
+                    i = next;
+                    continue;
+                }
+                Loop => {
+                    self.loops.insert(i, state.depth);
+                    state.top = None;
+                }
+                EndLoop(loc) => {
+                    return match self.loops.get(&loc) {
+                        Some(depth) if *depth != state.depth => Result::Err(Failure::Mismatch(
+                            format!("it changes the number of items by {} each time round a loop", state.depth - depth))),
+                        Some(_) => Ok(None),
+                        None => Result::Err(Failure::Unknown),
+                    };
+                }
+                GtZero | EqZero | FGtZero | FEqZero => {
+                    self.take(&mut state, 1)?;
+                    self.give(&mut state, [Some(Type::Bool)]);
+                }
+                IntToFloat | FloatToInt => {
+                    self.take(&mut state, 1)?;
+                    self.give(&mut state, [Some(Type::Int)]);
+                }
+                HeapAlloc => {
+                    self.take(&mut state, 1)?;
+                    self.give(&mut state, [Some(Type::Ptr)]);
+                }
+                HeapFree => _ = self.take(&mut state, 2)?,
+                Dup => {
+                    let amount = top.ok_or(Failure::Unknown)?;
+                    self.take(&mut state, 1)?;
+                    if amount > 0 {
+                        let kind = self.take(&mut state, 1)?[0];
+                        self.give(&mut state, std::iter::repeat_n(kind, amount.saturating_add(1).min(Value::from(u16::MAX)) as usize));
+                    } else if amount < 0 {
+                        self.take(&mut state, amount.saturating_neg())?;
+                    } else {
+                        // clears the stack, however many items are on it
+                        return Result::Err(Failure::Unknown);
+                    }
+                }
+                Swap => {
+                    self.take(&mut state, 1)?;
+                    // swaps the top item with the one from_top below it
+                    match top.filter(|from_top| *from_top >= 0) {
+                        Some(from_top) => {
+                            let mut types = self.take(&mut state, from_top.saturating_add(2))?;
+                            let last = types.len() - 1;
+                            types.swap(0, last);
+                            self.give(&mut state, types);
+                        }
+                        None => {
+                            self.take(&mut state, 2)?;
+                            // which items were swapped is not known
+                            state.types.clear();
+                            self.give(&mut state, [None, None]);
+                        }
+                    }
+                }
+                Op(op) => {
+                    let kind = if op.is_unary() {
+                        let operand = self.take(&mut state, 1)?;
+                        op_type(op, None, operand[0])
+                    } else {
+                        let operands = self.take(&mut state, 2)?;
+                        op_type(op, operands[0], operands[1])
+                    };
+                    self.give(&mut state, [kind]);
+                }
+                FOp(op) => {
+                    self.take(&mut state, if op == FloatOpCode::NEG { 1 } else { 2 })?;
+                    self.give(&mut state, [Some(Type::Int)]);
+                }
+                LateCall(_) | FuncStart(None) | FuncEnd | If(None) | ElseIf(_) | EndIf => return Result::Err(Failure::Unknown),
+            }
+            i += 1;
+        }
+        Ok(Some(state))
+    }
+
+    // the state after the if statement at loc, whose `(` jumps to target, and where the code after it starts
+    fn if_statement(&mut self, loc: usize, target: usize, mut state: State) -> Result<(Option<State>, usize), Failure> {
+        // a constant condition, eg. the `-1` of `)!(`, only ever takes one branch
+        let condition = state.top;
+        self.take(&mut state, 1)?;
+        let then = match condition {
+            Some(0) => None,
+            _ => self.run(loc + 1, target, state.clone())?,
+        };
+        let (other, next) = match self.context.codes[target] {
+            ProgramCode::EndIf => (Some(state), target + 1),
+            // `)!` followed by the condition of the next if statement in the chain
+            ProgramCode::ElseIf(Some(next_if)) => match (self.run(target + 1, next_if.get(), state)?, self.context.codes[next_if.get()]) {
+                (Some(condition), ProgramCode::If(Some(next_target))) => self.if_statement(next_if.get(), next_target.get(), condition)?,
+                _ => return Result::Err(Failure::Unknown),
+            },
+            _ => return Result::Err(Failure::Unknown),
+        };
+        let other = other.filter(|_| condition.is_none_or(|condition| condition == 0));
+        let joined = match (then, other) {
+            (Some(then), Some(other)) if then.depth != other.depth => return Result::Err(Failure::Mismatch(
+                format!("one branch of an if statement leaves {} more items than the other", (then.depth - other.depth).abs()))),
+            (Some(then), Some(other)) => {
+                // the types of the top items that both branches agree on
+                let types: Vec<Option<Type>> = then.types.iter().rev().zip(other.types.iter().rev())
+                    .map(|(then, other)| if then == other { *then } else { None })
+                    .collect();
+                Some(State { depth: then.depth, lowest: then.lowest.min(other.lowest), types: types.into_iter().rev().collect(), top: None })
+            }
+            (flow, None) | (None, flow) => flow,
+        };
+        Ok((joined, next))
+    }
+}
+//;
+
+// checks the code of the function starting at loc, which ends at end, against its annotated stack effect,
+// returning what is wrong with it, if anything
+pub fn check(codes: &[ProgramCode], loc: usize, end: usize, annotations: &Annotations, variables: &HashMap<String, Variable>) -> Option<String> {
+    let effect = annotations.effects.get(&loc)?;
+    let mut context = Context { codes, annotations, variables, inferred: HashMap::new(), inferring: HashSet::new() };
+    let mut checker = Checker { context: &mut context, inputs: Some(effect.inputs.len()), loops: HashMap::new() };
+    let start = State { depth: 0, lowest: 0, types: effect.inputs.iter().map(|input| input.kind).collect(), top: None };
+    match checker.run(loc + 1, end, start) {
+        Ok(Some(state)) if state.depth + effect.inputs.len() as Value != effect.outputs.len() as Value => {
+            Some(format!("it leaves {} items instead of {}", state.depth + effect.inputs.len() as Value, effect.outputs.len()))
+        }
+        Ok(Some(state)) => {
+            // the items it leaves, from the deepest up, lined up with the types of the items in the state
+            let known = &state.types[state.types.len().saturating_sub(effect.outputs.len())..];
+            let skipped = effect.outputs.len() - known.len();
+            effect.outputs[skipped..].iter().zip(known).find_map(|(output, given)| match (output.kind, given) {
+                (Some(expected), Some(given)) if !given.fits(expected) => {
+                    Some(format!("it leaves {} as {}, which should be {}", given.name(), output.name, expected.name()))
+                }
+                _ => None,
+            })
+        }
+        Result::Err(Failure::Mismatch(err)) => Some(err),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpret::{BlockKind, Interpreter}, lex::lex, parse::parse_program_code};
+
+    // compiles source, checking each annotated function just before the `]` that ends it (as the addresses of the variables in it
+    // are dropped once it ends) and returning what is wrong with each
+    fn compile(interpreter: &mut Interpreter, source: &str) -> Vec<String> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut errors: Vec<String> = Vec::new();
+        let mut parsed = 0;
+        for token in lex(source.chars()).into_iter().filter(|token| token.kind.is_word("]")) {
+            parse_program_code(chars[parsed..token.span.start].iter().copied(), interpreter.access_for_parsing(), true);
+            parsed = token.span.start;
+            let parser = interpreter.access_for_parsing();
+            if let Some(block) = parser.blocks.last().filter(|block| block.kind == BlockKind::Func && parser.macro_codes.is_empty()) {
+                errors.extend(check(parser.program_codes, block.loc, parser.program_codes.len(), parser.annotations, parser.variables));
+            }
+        }
+        parse_program_code(chars[parsed..].iter().copied(), interpreter.access_for_parsing(), true);
+        errors
+    }
+
+    fn errors(source: &str) -> Vec<String> {
+        compile(&mut Interpreter::new(), source)
+    }
+
+    #[test]
+    fn wrong_number_of_outputs() {
+        assert_eq!(errors("[f /*: a -- */ 1 ]"), ["it leaves 2 items instead of 0"]);
+        assert_eq!(errors("[f /*: a b -- c */ ]"), ["it leaves 2 items instead of 1"]);
+        assert_eq!(errors("[f /*: a b -- c */ + ]"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn too_many_inputs() {
+        assert_eq!(errors("[f /*: a -- b */ + ]"), ["it takes more than its 1 inputs"]);
+        assert_eq!(errors("[f /*: -- */ -1 >> ]"), ["it takes more than its 0 inputs"]);
+    }
+
+    #[test]
+    fn wrong_input_type() {
+        assert_eq!(errors("[g /*: p:ptr -- */ -1 >> ] [f /*: -- */ 1 g ]"), ["g takes p as ptr but is given int"]);
+        assert_eq!(errors("[g /*: p:ptr -- */ -1 >> ] [f /*: -- */ 1 #+ g ]"), Vec::<String>::new());
+        assert_eq!(errors("[f /*: -- p:ptr */ 1 ]"), ["it leaves int as p, which should be ptr"]);
+    }
+
+    #[test]
+    fn unbalanced_loop() {
+        assert_eq!(errors("[f /*: -- */ { 1 } ]"), ["it changes the number of items by 1 each time round a loop"]);
+        assert_eq!(errors("[f /*: n -- */ { -1 + 1 >> +@ ( } ) -1 >> ]"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn unbalanced_if() {
+        assert_eq!(errors("[f /*: a -- */ ( 1 ) ]"), ["one branch of an if statement leaves 1 more items than the other"]);
+        assert_eq!(errors("[f /*: a -- b */ ( 1 )! -1 ( 2 ) ]"), Vec::<String>::new());
+        // a constant condition only takes one branch
+        assert_eq!(errors("[f /*: -- b */ -1 ( 1 ) ]"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn unknown() {
+        // a late bound call could be anything
+        assert_eq!(errors("[f /*: -- a b */ @g ]"), Vec::<String>::new());
+        assert_eq!(errors("[f /*: -- a b */ later ]"), Vec::<String>::new());
+        // `0 >>` clears the stack, however many items are on it
+        assert_eq!(errors("[f /*: -- a */ 0 >> ]"), Vec::<String>::new());
+        // how many items `>>` copies or drops is only known if it is a constant
+        assert_eq!(errors("[f /*: n -- */ >> ]"), Vec::<String>::new());
+        // recursion without an annotation is not followed
+        assert_eq!(errors("[r r ] [f /*: -- a b */ r ]"), Vec::<String>::new());
+    }
+    #[test]
+    fn fits() {
+        assert!(Type::Int.fits(Type::Int));
+        assert!(Type::Bool.fits(Type::Int) && Type::Char.fits(Type::Int) && Type::Int.fits(Type::Char));
+        assert!(Type::Ptr.fits(Type::ListVar) && Type::Ptr.fits(Type::ArrayPtr) && Type::ListVar.fits(Type::Ptr));
+        assert!(!Type::Int.fits(Type::Ptr) && !Type::Bool.fits(Type::ListVar));
+        assert!(!Type::Ptr.fits(Type::Int) && !Type::ArrayPtr.fits(Type::Char));
+        assert!(!Type::ListVar.fits(Type::ArrayPtr) && !Type::ArrayPtr.fits(Type::ListVar));
+    }
+
+    #[test]
+    fn op_types() {
+        use OpCode::*;
+        let (int, bool, char, ptr) = (Some(Type::Int), Some(Type::Bool), Some(Type::Char), Some(Type::Ptr));
+        assert!(op_type(LT, ptr, ptr) == bool && op_type(EQ, None, None) == bool);
+        assert!(op_type(NOT, None, bool) == bool && op_type(NOT, None, int) == int);
+        assert!(op_type(AND, bool, bool) == bool && op_type(OR, bool, int) == int);
+        // an item that could be anything could be a pointer
+        assert!(op_type(ADD, None, int).is_none() && op_type(SUB, ptr, None).is_none());
+        assert!(op_type(ADD, ptr, int) == ptr && op_type(ADD, int, Some(Type::ArrayPtr)) == ptr && op_type(SUB, Some(Type::ListVar), int) == ptr);
+        // the difference between pointers is a number, and a number minus a pointer is not a pointer
+        assert!(op_type(SUB, ptr, ptr) == int && op_type(SUB, int, ptr) == int);
+        assert!(op_type(ADD, char, int) == char && op_type(ADD, int, char) == char && op_type(SUB, char, int) == char);
+        assert!(op_type(SUB, int, char) == int && op_type(MUL, ptr, int) == int);
+    }
+
+    #[test]
+    fn stdlib_signatures() {
+        let mut interpreter = Interpreter::new();
+        let stdlib = include_str!("../ss_src/stdlib/stdlib.ss");
+        assert_eq!(compile(&mut interpreter, stdlib), Vec::<String>::new());
+        // every function annotation is read
+        let annotated = stdlib.lines().filter(|line| line.contains("/*:") && line.contains("--")).count();
+        assert_eq!(interpreter.access_for_parsing().annotations.effects.len(), annotated);
+        let mut errors = |source: &str| compile(&mut interpreter, source);
+        errors(":nums /*: list-var */ :text /*: list-var */ :count");
+        assert_eq!(errors("[f /*: -- */ 1 nums list_append ]"), Vec::<String>::new());
+        assert_eq!(errors("[f /*: -- */ 1 2 list_append ]"), ["list_append takes list as list-var but is given int"]);
+        assert_eq!(errors("[f /*: -- */ 1 count list_append ]"), Vec::<String>::new());
+        assert_eq!(errors("[f /*: -- */ 5 #+ list_print ]"), Vec::<String>::new());
+        assert_eq!(errors("[f /*: -- */ 3 array_create_raw list_print ]"), ["list_print takes list as list-var but is given array-ptr"]);
+        assert_eq!(errors("[f /*: -- */ nums array_len -1 >> ]"), ["array_len takes array as array-ptr but is given list-var"]);
+        assert_eq!(errors("[f /*: -- n:int */ text str_to_num ]"), Vec::<String>::new());
+        assert_eq!(errors("[f /*: -- c:char */ 5 num_to_char ]"), Vec::<String>::new());
+        assert_eq!(errors("[f /*: -- p:ptr */ c'a' char_to_num ]"), ["it leaves int as p, which should be ptr"]);
+        assert_eq!(errors("[f /*: -- q:int r:int */ 7 2 divrem ]"), Vec::<String>::new());
+        assert_eq!(errors("[f /*: -- */ nums 3 divrem -2 >> ]"), ["divrem takes dividend as int but is given list-var"]);
+    }
+}
\ No newline at end of file
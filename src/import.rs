@@ -1,101 +1,878 @@
-use std::{fs::{self, File, ReadDir}, path::Path, io::{BufReader, BufRead}};
+use std::{collections::{HashMap, HashSet}, fs, path::{Path, PathBuf}, time::SystemTime};
 
-use super::interpret::Interpreter;
+use super::{interpret::{ForwardCalls, Interpreter, ProgramCode, Variable}, lex::TokenKind};
 
-pub struct ImportManager<'a, 'b> {
-    imports: Vec<String>,
-    paths: &'b Vec<&'a Path>
+pub struct ImportManager {
+    // canonical paths of the files that have been imported, in the order they finished importing
+    imports: Vec<PathBuf>,
+    // canonical paths of the files currently being imported, each a dependency of the one before
+    importing: Vec<PathBuf>,
+    // canonical paths of the dependencies of each imported file
+    dependencies: HashMap<PathBuf, Vec<PathBuf>>,
+    // the public names defined by each module
+    modules: HashMap<String, Vec<String>>,
+    // the modules that were imported with all of their public names bound unqualified
+    bound_modules: HashSet<String>,
+    // the aliases each module was imported as
+    aliases: HashMap<String, Vec<String>>,
+    // the canonical path of the file each module name belongs to, since modules are named after their files' names without their directories
+    module_paths: HashMap<String, PathBuf>,
+    // the versions declared in the headers of imported files
+    versions: HashMap<PathBuf, String>,
+    // when each imported file was last modified as of when it was compiled
+    modified: HashMap<PathBuf, SystemTime>,
+    // directories searched for imports, in order
+    paths: Vec<PathBuf>
 }
-impl ImportManager<'_, '_> {
-    pub fn new<'a, 'b>(paths: &'b Vec<&'a Path>) -> ImportManager<'a, 'b> {
-        ImportManager { imports: Vec::<String>::new(), paths }
+impl ImportManager {
+    pub fn new(paths: Vec<PathBuf>) -> ImportManager {
+        ImportManager { imports: Vec::new(), importing: Vec::new(), dependencies: HashMap::new(), modules: HashMap::new(), bound_modules: HashSet::new(), aliases: HashMap::new(), module_paths: HashMap::new(), versions: HashMap::new(), modified: HashMap::new(), paths }
     }
-}
 
-pub fn import_dir(manager: &mut ImportManager, interpreter: &mut Interpreter, path: &Path, compiler_optimise: bool) -> Result<(), ()> {
-    if !path.is_dir() {
-        super::print_err(format!("failed to import from directory {}: it is not a directory", path.display()));
-        return Err(());
+    // finds the file an import refers to, trying the directory of the file importing it, each search path then the current directory,
+    // with and without the .ss extension, and, if the name is of a directory, the file in it with the same name (eg. `stdlib` -> `stdlib/stdlib.ss`)
+    fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        self.resolve_from(path, self.importing.last().map(PathBuf::as_path))
     }
-    let dir: ReadDir;
-    if let Ok(val) = fs::read_dir(path) {
-        dir = val;
-    } else {
-        super::print_err(format!("failed to import from directory {}", path.file_name().unwrap_or_default().to_str().unwrap_or("[unknown]")));
-        return Err(());
+
+    // finds the file an import refers to as if it were a dependency of importer
+    fn resolve_from(&self, path: &Path, importer: Option<&Path>) -> Option<PathBuf> {
+        let mut candidates: Vec<PathBuf> = Vec::new();
+        let with_extension = |path: &Path| if path.extension().is_none() { Some(path.with_extension("ss")) } else { None };
+        let in_dir = |path: &Path| path.file_stem().map(|stem| path.with_extension("").join(stem).with_extension("ss"));
+        if path.is_relative() {
+            // embedded files come first so that they are not imported again from a copy on disk
+            let importer_dir = importer.and_then(Path::parent);
+            let search_dirs: Vec<&Path> = std::iter::once(Path::new(EMBEDDED_ROOT)).chain(importer_dir).chain(self.paths.iter().map(PathBuf::as_path)).collect();
+            candidates.extend(search_dirs.iter().map(|lib_path| lib_path.join(path)));
+            candidates.extend(search_dirs.iter().filter_map(|lib_path| with_extension(&lib_path.join(path))));
+            candidates.extend(search_dirs.iter().filter_map(|lib_path| in_dir(&lib_path.join(path))));
+        }
+        candidates.push(path.to_path_buf());
+        candidates.extend(with_extension(path));
+        candidates.extend(in_dir(path));
+        candidates.into_iter().find_map(|candidate| {
+            if embedded_file(&candidate).is_some() {
+                Some(candidate)
+            } else if candidate.is_file() {
+                fs::canonicalize(candidate).ok()
+            } else {
+                None
+            }
+        })
+    }
+
+    // the path of an imported file relative to the search path it was found in
+    fn display_name(&self, path: &Path) -> String {
+        if let Ok(embedded) = path.strip_prefix(EMBEDDED_ROOT) {
+            return format!("{} (embedded)", embedded.display());
+        }
+        self.paths.iter()
+            .filter_map(|lib_path| fs::canonicalize(lib_path).ok())
+            .find_map(|lib_path| path.strip_prefix(lib_path).ok().map(Path::to_path_buf))
+            .unwrap_or_else(|| path.to_path_buf())
+            .display().to_string()
     }
-    for file in dir {
-        if let Ok(val) = file {
-            if let Ok(()) = import_file(manager, interpreter, val.path().as_path(), compiler_optimise) {
+
+    pub fn print_paths(&self) {
+        for path in &self.paths {
+            println!("{}", path.display());
+        }
+    }
+
+    // the imported files that have been modified since they were compiled
+    fn changed_files(&self) -> Vec<PathBuf> {
+        self.imports.iter()
+            .filter(|path| modified_time(path).is_some_and(|modified| self.modified.get(*path) != Some(&modified)))
+            .cloned()
+            .collect()
+    }
+
+    pub fn print_dependencies(&self) {
+        for path in &self.imports {
+            let dependencies: Vec<String> = self.dependencies.get(path).into_iter().flatten().map(|dependency| self.display_name(dependency)).collect();
+            if dependencies.is_empty() {
+                println!("{}", self.display_name(path));
             } else {
+                println!("{} -> {}", self.display_name(path), dependencies.join(", "));
             }
         }
     }
+}
 
-    Ok(())
+//: search path
+pub const PATH_VAR: &str = "STACK_SHARP_PATH";
+pub const DEFAULT_PATH: &str = "ss_src";
+
+// the user-level library directory, ~/.stack_sharp/lib, if it exists
+pub fn user_lib_dir() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    let dir = Path::new(&home).join(".stack_sharp").join("lib");
+    if dir.is_dir() { Some(dir) } else { None }
 }
 
-pub fn import_file(manager: &mut ImportManager, interpreter: &mut Interpreter, path: &Path, compiler_optimise: bool) -> Result<(), ()> {
-    if path.is_relative() {
-        for lib_path in manager.paths {
-            let file_path = lib_path.join(&path);
-            if file_path.is_file() {
-                if import_file(manager, interpreter, &file_path, compiler_optimise).is_ok() {
-                    return Ok(());
+// the directories given on the command line, then those in STACK_SHARP_PATH, then ss_src and finally the user-level library directory
+pub fn search_paths(cli_paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut paths = cli_paths;
+    if let Some(env_paths) = std::env::var_os(PATH_VAR) {
+        paths.extend(std::env::split_paths(&env_paths).filter(|path| !path.as_os_str().is_empty()));
+    }
+    paths.push(PathBuf::from(DEFAULT_PATH));
+    paths.extend(user_lib_dir());
+    paths
+}
+//;
+
+//: embedded files
+// the root of the virtual directory containing the files built into the interpreter
+pub const EMBEDDED_ROOT: &str = "<embedded>";
+const EMBEDDED: &[(&str, &str)] = &[
+    ("stdlib/stdlib.ss", include_str!("../ss_src/stdlib/stdlib.ss")),
+];
+
+fn embedded_file(path: &Path) -> Option<&'static str> {
+    let path = path.strip_prefix(EMBEDDED_ROOT).ok()?;
+    EMBEDDED.iter().find(|(name, _)| Path::new(name) == path).map(|(_, source)| *source)
+}
+
+fn embedded_dir(path: &Path) -> Option<Vec<PathBuf>> {
+    let dir = path.strip_prefix(EMBEDDED_ROOT).ok()?;
+    Some(EMBEDDED.iter()
+        .filter(|(name, _)| Path::new(name).parent() == Some(dir))
+        .map(|(name, _)| Path::new(EMBEDDED_ROOT).join(name))
+        .collect())
+}
+//;
+
+//: namespaces
+fn module_name(path: &Path) -> String {
+    path.file_stem().unwrap_or_default().to_str().unwrap_or("[unknown]").to_owned()
+}
+
+fn is_private(name: &str) -> bool {
+    name.starts_with('_')
+}
+
+// private names have a space after the `::` so that they can never be written as a single word,
+// which unmangle drops along with the numbers of renamed names, so that they are shown as `module::_name`
+fn qualified_name(module: &str, name: &str) -> String {
+    if is_private(name) {
+        format!("{}:: {}", module, name)
+    } else {
+        format!("{}::{}", module, name)
+    }
+}
+
+// qualifies a word in a macro body if it refers to a name defined in the module, keeping any `;`, `@` or `*` prefix
+fn qualify_word(module: &str, names: &HashSet<String>, word: &str) -> Option<String> {
+    let prefix_len = if word.starts_with(';') || word.starts_with('@') { 1 } else { word.chars().take_while(|c| *c == '*').count() };
+    let (prefix, name) = word.split_at(prefix_len);
+    if names.contains(name) {
+        Some(format!("{}{}", prefix, qualified_name(module, name)))
+    } else {
+        None
+    }
+}
+
+// moves the definitions made by a module to their qualified names, restoring whatever they replaced,
+// late bound calls compiled from the module's code from late_calls_start on are also moved to the qualified names
+fn namespace_definitions(manager: &mut ImportManager, interpreter: &mut Interpreter, module: &str, definitions: Vec<(String, Option<Variable>)>, late_calls_start: usize) {
+    let names: HashSet<String> = definitions.iter().map(|(name, _)| name.clone()).collect();
+    for name in interpreter.late_calls_mut()[late_calls_start..].iter_mut() {
+        if names.contains(name) {
+            *name = qualified_name(module, name);
+        }
+    }
+    let variables = interpreter.variables_mut();
+    let mut public: Vec<String> = Vec::new();
+    for (name, previous) in definitions {
+        if let Some(mut value) = variables.remove(&name) {
+            // macros are expanded where they are used, so must refer to the module's names by their qualified names
+            if let Variable::Macro(words, _) = &mut value {
+                for word in words.iter_mut() {
+                    if let TokenKind::Word(word) = word {
+                        if let Some(qualified) = qualify_word(module, &names, word) {
+                            *word = qualified;
+                        }
+                    }
                 }
             }
+            variables.insert(qualified_name(module, &name), value);
+        }
+        if let Some(previous) = previous {
+            variables.insert(name.clone(), previous);
+        }
+        if !is_private(&name) {
+            public.push(name);
         }
     }
-    if path.extension() == None {
-        if let Ok(_) = import_file(manager, interpreter, &path.with_extension("ss"), compiler_optimise) {
-            return Ok(());
+    manager.modules.insert(module.to_owned(), public);
+}
+
+// binds the selected public names (or all of them) of an imported module to their unqualified names
+fn bind_module(manager: &ImportManager, interpreter: &mut Interpreter, module: &str, selection: Option<&[String]>) -> Result<(), ()> {
+    let public = manager.modules.get(module).map(Vec::as_slice).unwrap_or_default();
+    let variables = interpreter.variables_mut();
+    let mut result = Ok(());
+    for name in selection.unwrap_or(public) {
+        if let (true, Some(value)) = (public.contains(name), variables.get(&qualified_name(module, name))) {
+            variables.insert(name.clone(), value.clone());
+        } else {
+            super::print_err(format!("{} is not a public definition in {}", name, module));
+            result = Err(());
+        }
+    }
+    result
+}
+
+// gives a module's public names qualified by an alias as well as by its name
+fn alias_module(manager: &mut ImportManager, interpreter: &mut Interpreter, module: &str, alias: &str) {
+    let public = manager.modules.get(module).cloned().unwrap_or_default();
+    let variables = interpreter.variables_mut();
+    for name in &public {
+        if let Some(value) = variables.get(&qualified_name(module, name)) {
+            variables.insert(qualified_name(alias, name), value.clone());
         }
     }
-    if !path.is_file() {
-        super::print_err(format!("failed to import from {}: it is not a file", path.display()));
+    manager.modules.insert(alias.to_owned(), public);
+}
+//;
+
+//: file headers
+// an import such as `fib@1.2 as f {fastfib}`
+struct ImportSpec {
+    name: String,
+    // the version the imported file must declare, where `1` accepts `1`, `1.0` and `1.2.3`
+    version: Option<String>,
+    alias: Option<String>,
+    // names to bind unqualified, instead of all of them
+    selection: Option<Vec<String>>,
+}
+
+struct Header {
+    imports: Vec<ImportSpec>,
+    version: Option<String>,
+    // the number of lines at the start of the file that are part of the header
+    lines: usize,
+}
+
+fn is_version(version: &str) -> bool {
+    version.split('.').all(|part| !part.is_empty() && part.chars().all(|chr| chr.is_ascii_digit()))
+}
+
+fn version_matches(required: &str, version: &str) -> bool {
+    let mut version = version.split('.');
+    required.split('.').all(|part| version.next().and_then(|num| num.parse::<u64>().ok()) == part.parse::<u64>().ok())
+}
+
+// collects the names in a selection such as `{print_num list_append}`, which may be split over multiple arguments
+fn parse_selection<'a>(imports: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>) -> Result<Option<Vec<String>>, String> {
+    if !imports.peek().is_some_and(|arg| arg.starts_with('{')) {
+        return Ok(None);
+    }
+    let mut selection: Vec<String> = Vec::new();
+    for arg in imports.by_ref() {
+        let closed = arg.ends_with('}');
+        selection.extend(arg.trim_start_matches('{').trim_end_matches('}').split(',').filter(|name| !name.is_empty()).map(str::to_owned));
+        if closed {
+            return Ok(Some(selection));
+        }
+    }
+    Err("expected a `}` to close the selection".to_owned())
+}
+
+// parses a list of imports, each a file name optionally followed by `@<version>`, then `as <alias>`, then a selection
+fn parse_imports<'a>(imports: impl IntoIterator<Item = &'a str>) -> Result<Vec<ImportSpec>, String> {
+    let mut imports = imports.into_iter().peekable();
+    let mut specs: Vec<ImportSpec> = Vec::new();
+    while let Some(import) = imports.next() {
+        if import.starts_with('{') || import == "as" {
+            return Err(format!("expected a file name before `{}`", import));
+        }
+        let (name, version) = match import.split_once('@') {
+            Some((name, version)) if is_version(version) => (name, Some(version.to_owned())),
+            Some((_, version)) => return Err(format!("invalid version `{}` for {} (expected numbers separated by `.`s, eg. 1.2)", version, import)),
+            None => (import, None),
+        };
+        let mut alias: Option<String> = None;
+        if imports.next_if_eq(&"as").is_some() {
+            match imports.next() {
+                Some(name) if !name.starts_with('{') && !name.contains(':') && !is_private(name) => alias = Some(name.to_owned()),
+                _ => return Err(format!("expected a module name after `{} as`", import)),
+            }
+        }
+        let selection = parse_selection(&mut imports)?;
+        specs.push(ImportSpec { name: name.to_owned(), version, alias, selection });
+    }
+    Ok(specs)
+}
+
+// the rest of a line after a header keyword, if the line starts with it followed by a `:`, whitespace or nothing,
+// so that comments such as `//depth-first search` are not mistaken for headers
+fn strip_keyword<'a>(line: &'a str, keyword: &str) -> Option<&'a str> {
+    line.strip_prefix(keyword).filter(|rest| rest.is_empty() || rest.starts_with(':') || rest.starts_with(char::is_whitespace))
+}
+
+// parses the leading lines of a file that are blank, import dependencies (`//dep:`, `///dep:` or `///import` followed by imports)
+// or declare its version (`//version: 1.2`)
+fn parse_header(source: &str) -> Result<Header, String> {
+    let mut header = Header { imports: Vec::new(), version: None, lines: 0 };
+    for (line_num, line) in source.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+        let malformed = |err: String| format!("malformed header on line {}: {}", line_num, err);
+        if let Some(rest) = ["///import", "///dep", "//dep"].iter().find_map(|keyword| strip_keyword(line, keyword)) {
+            let imports = match rest.strip_prefix(':') {
+                Some(_) if line.starts_with("///import") => return Err(malformed(format!("expected whitespace after ///import, not {}", rest))),
+                Some(imports) => imports,
+                None if line.starts_with("///import") => rest,
+                None => return Err(malformed("expected a `:` after the keyword".to_owned())),
+            };
+            let imports = parse_imports(imports.split_ascii_whitespace()).map_err(malformed)?;
+            if imports.is_empty() {
+                return Err(malformed("expected file names to import".to_owned()));
+            }
+            header.imports.extend(imports);
+        } else if let Some(rest) = strip_keyword(line, "//version").or_else(|| strip_keyword(line, "///version")) {
+            let Some(version) = rest.strip_prefix(':').map(str::trim) else {
+                return Err(malformed("expected a `:` after the keyword".to_owned()));
+            };
+            if header.version.is_some() {
+                return Err(malformed("the version is declared more than once".to_owned()));
+            } else if !is_version(version) {
+                return Err(malformed(format!("invalid version `{}` (expected numbers separated by `.`s, eg. 1.2)", version)));
+            }
+            header.version = Some(version.to_owned());
+        } else if !line.is_empty() {
+            break;
+        }
+        header.lines = line_num;
+    }
+    Ok(header)
+}
+//;
+
+// imports every file in a directory in name order, except that files are imported after any others in the directory that they depend on,
+// continuing past files that fail to import and reporting them all at the end
+pub fn import_dir(manager: &mut ImportManager, interpreter: &mut Interpreter, path: &Path, compiler_optimise: bool) -> Result<(), ()> {
+    let mut files: Vec<PathBuf>;
+    if let Some(embedded) = embedded_dir(path) {
+        files = embedded;
+    } else if !path.is_dir() {
+        super::print_err(format!("failed to import from directory {}: it is not a directory", path.display()));
+        return Err(());
+    } else if let Ok(dir) = fs::read_dir(path) {
+        files = dir.flatten()
+            .map(|file| file.path())
+            .filter(|path| path.is_file())
+            .filter_map(|path| fs::canonicalize(path).ok())
+            .collect();
+    } else {
+        super::print_err(format!("failed to import from directory {}", path.file_name().unwrap_or_default().to_str().unwrap_or("[unknown]")));
+        return Err(());
+    }
+    files.sort();
+
+    let mut failed: Vec<String> = Vec::new();
+    for path in dependency_order(manager, &files) {
+        if manager.imports.contains(&path) {
+            continue;
+        }
+        if import_file(manager, interpreter, &path, compiler_optimise).is_ok() {
+            _ = bind_module(manager, interpreter, &module_name(&path), None);
+        } else {
+            failed.push(manager.display_name(&path));
+        }
+    }
+    if !failed.is_empty() {
+        super::print_err(format!("failed to import {} of {} files from {}: {}", failed.len(), files.len(), path.display(), failed.join(", ")));
+        return Err(());
+    }
+    Ok(())
+}
+
+// sorts files so that each comes after the files it depends on, keeping them in the given order where possible,
+// files in a dependency cycle are left in the given order for import_file to report
+fn dependency_order(manager: &ImportManager, files: &[PathBuf]) -> Vec<PathBuf> {
+    // the indices of the files in the list that each file depends on
+    let mut dependencies: Vec<Vec<usize>> = files.iter().map(|path| {
+        // files with malformed headers have no dependencies here, the error is reported when they are imported
+        let imports = read_source(path).and_then(|source| parse_header(&source).ok()).map(|header| header.imports).unwrap_or_default();
+        imports.iter()
+            .filter_map(|import| manager.resolve_from(Path::new(&import.name), Some(path)))
+            .filter_map(|dependency| files.iter().position(|file| *file == dependency))
+            .collect()
+    }).collect();
+
+    let mut order: Vec<PathBuf> = Vec::new();
+    let mut done: Vec<bool> = vec![false; files.len()];
+    while order.len() < files.len() {
+        // the first file whose dependencies have all been ordered, or the first remaining file if they are in a cycle
+        let next = (0..files.len()).find(|&i| !done[i] && dependencies[i].iter().all(|&dependency| done[dependency]))
+            .or_else(|| (0..files.len()).find(|&i| !done[i]))
+            .unwrap_or_default();
+        done[next] = true;
+        dependencies[next].clear();
+        order.push(files[next].clone());
+    }
+    order
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn read_source(path: &Path) -> Option<String> {
+    match embedded_file(path) {
+        Some(embedded) => Some(embedded.to_owned()),
+        None => fs::read_to_string(path).ok(),
+    }
+}
+
+// imports the file at a canonical path, first importing its dependencies
+pub fn import_file(manager: &mut ImportManager, interpreter: &mut Interpreter, path: &Path, compiler_optimise: bool) -> Result<(), ()> {
+    if let Some(start) = manager.importing.iter().position(|importing| importing == path) {
+        let cycle: Vec<String> = manager.importing[start..].iter().chain(std::iter::once(&path.to_path_buf())).map(|path| manager.display_name(path)).collect();
+        super::print_err(format!("failed to import {}: circular dependency {}", manager.display_name(path), cycle.join(" -> ")));
         return Err(());
     }
-    let file: File;
-    if let Ok(val) = fs::File::open(path) {
-        file = val;
+    let module = module_name(path);
+    if let Some(other) = manager.module_paths.get(&module).filter(|other| *other != path) {
+        super::print_err(format!("failed to import {}: its module name {} is already used by {}", manager.display_name(path), module, manager.display_name(other)));
+        return Err(());
+    }
+    manager.importing.push(path.to_path_buf());
+    let result = compile_file(manager, interpreter, path, compiler_optimise);
+    manager.importing.pop();
+    if result.is_ok() {
+        manager.imports.push(path.to_path_buf());
+        manager.module_paths.insert(module, path.to_path_buf());
+    }
+    result
+}
+
+// reports each block a file leaves open and closes it, so that it does not swallow the code after the file
+fn close_blocks(interpreter: &mut Interpreter, file_name: &str) {
+    while let Some(block) = interpreter.blocks_mut().pop() {
+        super::print_err(format!("{}: unclosed `{}` at line {} column {}", file_name, block.kind.opener(), block.span.line, block.span.column));
+        super::parse::close_block(block, interpreter.access_for_parsing().program_codes);
+    }
+}
+
+fn compile_file(manager: &mut ImportManager, interpreter: &mut Interpreter, path: &Path, compiler_optimise: bool) -> Result<(), ()> {
+    let source: String;
+    if let Some(val) = read_source(path) {
+        source = val;
     } else {
         super::print_err(format!("failed to import {}", path.file_name().unwrap_or_default().to_str().unwrap_or("[unknown]")));
         return Err(());
     }
-    let mut reader = BufReader::new(file);
-    let mut first_line: String = String::new();
-    match reader.read_line(&mut first_line) {
-        Err(_) => {
-            super::print_err(format!("failed to import {}", path.file_name().unwrap_or_default().to_str().unwrap_or("[unknown]")));
-            return Err(());
-        }
-        Ok(0) => {
-            super::print_err(format!("failed to import {}: file is empty", path.file_name().unwrap_or_default().to_str().unwrap_or("[unknown]")));
+    if source.is_empty() {
+        super::print_err(format!("failed to import {}: file is empty", path.file_name().unwrap_or_default().to_str().unwrap_or("[unknown]")));
+        return Err(());
+    }
+    let header = match parse_header(&source) {
+        Ok(header) => header,
+        Err(err) => {
+            super::print_err(format!("failed to import {}: {}", path.file_name().unwrap_or_default().to_str().unwrap_or("[unknown]"), err));
             return Err(());
         }
-        _ => ()
     };
-    let lines;
-    if first_line.starts_with("//dep:") {
-        let dependencies = first_line[6..].split_ascii_whitespace();
-        if let Err(_) = import_multiple(manager, interpreter, dependencies, compiler_optimise) {
-            super::print_err(format!("failed to import {}: failed to import dependencies", path.file_name().unwrap_or_default().to_str().unwrap_or("[unknown]")));
-            return Err(());
-        }
-        first_line = String::new();
+    if let Some(version) = header.version {
+        manager.versions.insert(path.to_path_buf(), version);
     }
-    lines = std::iter::once(first_line).chain(reader.lines().map(|x| x.unwrap_or_default()));
-    super::parse::parse_program_code(lines.flat_map(|x| x.chars().chain(std::iter::once('\n')).collect::<Vec<_>>()), interpreter.access_for_parsing(), compiler_optimise);
-    manager.imports.push(path.file_stem().unwrap_or_default().to_str().unwrap_or("[unknown]").to_owned());
+    if let Some(modified) = modified_time(path) {
+        manager.modified.insert(path.to_path_buf(), modified);
+    }
+    if import_specs(manager, interpreter, &header.imports, compiler_optimise).is_err() {
+        super::print_err(format!("failed to import {}: failed to import dependencies", path.file_name().unwrap_or_default().to_str().unwrap_or("[unknown]")));
+        return Err(());
+    }
+    // the header is replaced by empty lines so that line numbers stay the same
+    let body: String = "\n".repeat(header.lines) + &source.split_inclusive('\n').skip(header.lines).collect::<String>();
+    let module = module_name(path);
+    interpreter.record_definitions();
+    let late_calls_start = interpreter.late_calls().len();
+    let outer_forward_calls = interpreter.replace_forward_calls(ForwardCalls { in_file: true, ..ForwardCalls::default() });
+    let outer_blocks = interpreter.replace_blocks(Vec::new());
+    super::parse::parse_program_code(body.chars(), interpreter.access_for_parsing(), compiler_optimise);
+    close_blocks(interpreter, path.file_name().unwrap_or_default().to_str().unwrap_or("[unknown]"));
+    interpreter.replace_blocks(outer_blocks);
+    for name in interpreter.replace_forward_calls(outer_forward_calls).unresolved() {
+        super::print_err(format!("{}: {} is called or declared but never defined", path.file_name().unwrap_or_default().to_str().unwrap_or("[unknown]"), name));
+    }
+    let definitions = interpreter.stop_recording_definitions();
+    namespace_definitions(manager, interpreter, &module, definitions, late_calls_start);
     Ok(())
 }
 
+// imports each file, binding only the names in a selection (eg. `stdlib {print_num list_append}`) if one follows it
 pub fn import_multiple<'a>(manager: &mut ImportManager, interpreter: &mut Interpreter, imports: impl std::iter::IntoIterator<Item = &'a str>, compiler_optimise: bool) -> Result<(), ()> {
+    match parse_imports(imports) {
+        Ok(specs) => import_specs(manager, interpreter, &specs, compiler_optimise),
+        Err(err) => {
+            super::print_err(err);
+            Err(())
+        }
+    }
+}
+
+fn import_specs(manager: &mut ImportManager, interpreter: &mut Interpreter, imports: &[ImportSpec], compiler_optimise: bool) -> Result<(), ()> {
     for import in imports {
-        let name = import.to_owned();
-        if !manager.imports.contains(&name) {
-            import_file(manager, interpreter, Path::new(&name), compiler_optimise)?;
+        let path = match manager.resolve(Path::new(&import.name)) {
+            Some(path) => path,
+            None => {
+                super::print_err(format!("failed to import {}: no such file", import.name));
+                return Err(());
+            }
+        };
+        if let Some(importer) = manager.importing.last() {
+            let dependencies = manager.dependencies.entry(importer.clone()).or_default();
+            if !dependencies.contains(&path) {
+                dependencies.push(path.clone());
+            }
+        }
+        let module = module_name(&path);
+        let imported = manager.imports.contains(&path);
+        if let Some(required) = &import.version {
+            // checked before the file is compiled so that a file of the wrong version is not left loaded,
+            // a file with a malformed header is left for import_file to report
+            let declared = if imported {
+                Some(manager.versions.get(&path).cloned())
+            } else {
+                read_source(&path).and_then(|source| parse_header(&source).ok()).map(|header| header.version)
+            };
+            match declared.as_ref().map(Option::as_deref) {
+                Some(Some(version)) if version_matches(required, version) => {}
+                Some(Some(version)) => {
+                    super::print_err(format!("failed to import {}: version {} is required but {} is version {}", import.name, required, manager.display_name(&path), version));
+                    return Err(());
+                }
+                Some(None) => {
+                    super::print_err(format!("failed to import {}: version {} is required but {} does not declare a version", import.name, required, manager.display_name(&path)));
+                    return Err(());
+                }
+                None => {}
+            }
+        }
+        if !imported {
+            import_file(manager, interpreter, &path, compiler_optimise)?;
+        }
+        if let Some(alias) = &import.alias {
+            if let Some(other) = manager.module_paths.get(alias).filter(|other| **other != path) {
+                super::print_err(format!("failed to import {} as {}: {} is the name of {}", import.name, alias, alias, manager.display_name(other)));
+                return Err(());
+            }
+            alias_module(manager, interpreter, &module, alias);
+            let aliases = manager.aliases.entry(module.clone()).or_default();
+            if !aliases.contains(alias) {
+                aliases.push(alias.clone());
+            }
+        }
+        // an aliased module is only bound by its alias unless names are selected
+        if import.selection.is_some() || (!imported && import.alias.is_none()) {
+            bind_module(manager, interpreter, &module, import.selection.as_deref())?;
+            if import.selection.is_none() {
+                manager.bound_modules.insert(module);
+            }
+        }
+    }
+    Ok(())
+}
+
+//: reloading
+// the target of a jump relative to the start of the function it is in, so that the same code compiled elsewhere is equal
+fn relative_jump(code: &ProgramCode, start: usize) -> Option<usize> {
+    match code {
+        ProgramCode::FuncStart(Some(loc)) | ProgramCode::If(Some(loc)) | ProgramCode::ElseIf(Some(loc)) => Some(loc.get().wrapping_sub(start)),
+        ProgramCode::EndLoop(loc) => Some(loc.wrapping_sub(start)),
+        _ => None,
+    }
+}
+
+// whether two definitions of a name do the same thing, where calls to functions are the same if they are to functions with the same name
+fn same_definition(interpreter: &Interpreter, old: &Variable, new: &Variable, old_names: &HashMap<usize, &str>, new_names: &HashMap<usize, &str>) -> bool {
+    match (old, new) {
+        (Variable::Function(old_loc), Variable::Function(new_loc)) => {
+            let (old_code, new_code) = (interpreter.function_code(*old_loc), interpreter.function_code(*new_loc));
+            old_code.len() == new_code.len() && old_code.iter().zip(new_code).all(|codes| match codes {
+                (ProgramCode::FuncCall(old_call), ProgramCode::FuncCall(new_call)) => {
+                    old_call == new_call || old_names.get(old_call).is_some_and(|name| new_names.get(new_call) == Some(name))
+                }
+                (ProgramCode::LateCall(old_call), ProgramCode::LateCall(new_call)) => interpreter.late_calls()[*old_call] == interpreter.late_calls()[*new_call],
+                (old_code, new_code) => match (relative_jump(old_code, *old_loc), relative_jump(new_code, *new_loc)) {
+                    (Some(old_jump), Some(new_jump)) => std::mem::discriminant(old_code) == std::mem::discriminant(new_code) && old_jump == new_jump,
+                    _ => old_code == new_code,
+                }
+            })
+        }
+        (old, new) => old == new,
+    }
+}
+
+// the (name, definition) of each public definition in a module
+fn module_definitions(manager: &ImportManager, interpreter: &mut Interpreter, module: &str) -> Vec<(String, Variable)> {
+    let variables = interpreter.variables_mut();
+    manager.modules.get(module).into_iter().flatten()
+        .filter_map(|name| variables.get(&qualified_name(module, name)).map(|value| (name.clone(), value.clone())))
+        .collect()
+}
+
+// recompiles an imported file, then rebinds every name (unqualified or aliased) that referred to one of its old definitions
+fn reload_file(manager: &mut ImportManager, interpreter: &mut Interpreter, path: &Path, compiler_optimise: bool) -> Result<(), ()> {
+    let module = module_name(path);
+    let old = module_definitions(manager, interpreter, &module);
+    manager.importing.push(path.to_path_buf());
+    let result = compile_file(manager, interpreter, path, compiler_optimise);
+    manager.importing.pop();
+    result?;
+    let new = module_definitions(manager, interpreter, &module);
+
+    let function_names = |definitions: &'_ [(String, Variable)]| -> HashMap<usize, String> {
+        definitions.iter().filter_map(|(name, value)| if let Variable::Function(loc) = value { Some((*loc, name.clone())) } else { None }).collect()
+    };
+    let (old_functions, new_functions) = (function_names(&old), function_names(&new));
+    let old_names: HashMap<usize, &str> = old_functions.iter().map(|(loc, name)| (*loc, name.as_str())).collect();
+    let new_names: HashMap<usize, &str> = new_functions.iter().map(|(loc, name)| (*loc, name.as_str())).collect();
+
+    let (mut changed, mut added, mut removed): (Vec<&str>, Vec<&str>, Vec<&str>) = (Vec::new(), Vec::new(), Vec::new());
+    for (name, new_value) in &new {
+        match old.iter().find(|(old_name, _)| old_name == name) {
+            Some((_, old_value)) => {
+                if !same_definition(interpreter, old_value, new_value, &old_names, &new_names) {
+                    changed.push(name);
+                }
+                rebind(interpreter, name, old_value, Some(new_value));
+            }
+            None => added.push(name),
+        }
+    }
+    for (name, old_value) in &old {
+        if !new.iter().any(|(new_name, _)| new_name == name) {
+            removed.push(name);
+            rebind(interpreter, name, old_value, None);
+        }
+    }
+    // added names are bound wherever all of the module's names were when it was imported
+    if manager.bound_modules.contains(&module) {
+        let added: Vec<String> = added.iter().map(|name| name.to_string()).collect();
+        bind_module(manager, interpreter, &module, Some(&added))?;
+    }
+    for alias in manager.aliases.get(&module).cloned().unwrap_or_default() {
+        alias_module(manager, interpreter, &module, &alias);
+    }
+
+    let mut report: Vec<String> = Vec::new();
+    for (kind, names) in [("changed", changed), ("added", added), ("removed", removed)] {
+        if !names.is_empty() {
+            report.push(format!("{} {}", kind, names.join(" ")));
         }
     }
+    if report.is_empty() {
+        println!("reloaded {}: no definitions changed", manager.display_name(path));
+    } else {
+        println!("reloaded {}: {}", manager.display_name(path), report.join(", "));
+    }
     Ok(())
+}
+
+// replaces (or removes) every binding of a name, or of an alias' qualified name, that refers to its old definition
+fn rebind(interpreter: &mut Interpreter, name: &str, old: &Variable, new: Option<&Variable>) {
+    let qualified_suffix = format!("::{}", name);
+    let variables = interpreter.variables_mut();
+    let bound: Vec<String> = variables.iter()
+        .filter(|(key, value)| (key.as_str() == name || key.ends_with(&qualified_suffix)) && *value == old)
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in bound {
+        match new {
+            Some(new) => variables.insert(key, new.clone()),
+            None => variables.remove(&key),
+        };
+    }
+}
+
+// reloads each of the files, or every imported file that has changed if none are given
+pub fn reload<'a>(manager: &mut ImportManager, interpreter: &mut Interpreter, names: impl IntoIterator<Item = &'a str>, compiler_optimise: bool) -> Result<(), ()> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for name in names {
+        match manager.resolve(Path::new(name)) {
+            Some(path) if embedded_file(&path).is_some() => {
+                super::print_err(format!("failed to reload {}: it is built into the interpreter", name));
+                return Err(());
+            }
+            Some(path) if manager.imports.contains(&path) => paths.push(path),
+            _ => {
+                super::print_err(format!("failed to reload {}: it has not been imported", name));
+                return Err(());
+            }
+        }
+    }
+    if paths.is_empty() {
+        paths = manager.changed_files();
+        if paths.is_empty() {
+            println!("no imported files have changed");
+        }
+    }
+    let mut result = Ok(());
+    for path in paths {
+        if reload_file(manager, interpreter, &path, compiler_optimise).is_err() {
+            super::print_err(format!("failed to reload {}", manager.display_name(&path)));
+            result = Err(());
+        }
+    }
+    result
+}
+
+// reloads the imported files that have changed, if there are any
+pub fn reload_changed(manager: &mut ImportManager, interpreter: &mut Interpreter, compiler_optimise: bool) {
+    if !manager.changed_files().is_empty() {
+        _ = reload(manager, interpreter, [], compiler_optimise);
+    }
+}
+//;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpret::Cell, parse::parse_program_code};
+
+    // a directory of files for a test, which is emptied first
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stack_sharp_{}_{}", name, std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn run(interpreter: &mut Interpreter, source: &str) -> Option<Cell> {
+        parse_program_code(source.chars(), interpreter.access_for_parsing(), false);
+        interpreter.interpret();
+        interpreter.data_stack.try_pop()
+    }
+
+    #[test]
+    fn reload_binds_added_names() {
+        let dir = temp_dir("reload");
+        fs::write(dir.join("util.ss"), "[double 2 * ]").unwrap();
+        fs::write(dir.join("picked.ss"), "[half 2 / ]").unwrap();
+        let mut manager = ImportManager::new(vec![dir.clone()]);
+        let mut interpreter = Interpreter::new();
+        import_multiple(&mut manager, &mut interpreter, ["util", "util", "as", "u", "picked", "{half}"], false).unwrap();
+        fs::write(dir.join("util.ss"), "[double 2 * ] [quad 4 * ]").unwrap();
+        fs::write(dir.join("picked.ss"), "[half 2 / ] [third 3 / ]").unwrap();
+        reload(&mut manager, &mut interpreter, ["util", "picked"], false).unwrap();
+        assert_eq!(run(&mut interpreter, "1 quad"), Some(Cell::Int(4)));
+        assert_eq!(run(&mut interpreter, "1 util::quad"), Some(Cell::Int(4)));
+        assert_eq!(run(&mut interpreter, "1 u::quad"), Some(Cell::Int(4)));
+        // only the selected names of a module are bound unqualified
+        assert!(interpreter.is_defined("picked::third"));
+        assert!(!interpreter.is_defined("third"));
+        _ = fs::remove_dir_all(dir);
+    }
+
+    fn import_names(header: &Header) -> Vec<&str> {
+        header.imports.iter().map(|import| import.name.as_str()).collect()
+    }
+
+    #[test]
+    fn headers() {
+        let header = parse_header("//dep: fib util@1.2\n\n///import stdlib {print_num}\n//version: 2.0\n[main ]\n//dep: late").unwrap();
+        assert_eq!(import_names(&header), ["fib", "util", "stdlib"]);
+        assert_eq!(header.imports[1].version.as_deref(), Some("1.2"));
+        assert_eq!(header.imports[2].selection, Some(vec!["print_num".to_owned()]));
+        assert_eq!(header.version.as_deref(), Some("2.0"));
+        // the header ends at the first line that is not part of it
+        assert_eq!(header.lines, 4);
+        // a comment starting with a keyword is not a header
+        let header = parse_header("//depth-first search\n//dep: fib").unwrap();
+        assert!(header.imports.is_empty());
+        assert_eq!(header.lines, 0);
+        assert_eq!(parse_header("//versioned\n").unwrap().version, None);
+    }
+
+    #[test]
+    fn malformed_headers() {
+        assert_eq!(parse_header("//dep fib").err().unwrap(), "malformed header on line 1: expected a `:` after the keyword");
+        assert_eq!(parse_header("///import: fib").err().unwrap(), "malformed header on line 1: expected whitespace after ///import, not : fib");
+        assert_eq!(parse_header("\n//dep:").err().unwrap(), "malformed header on line 2: expected file names to import");
+        assert_eq!(parse_header("//version: 1.x").err().unwrap(), "malformed header on line 1: invalid version `1.x` (expected numbers separated by `.`s, eg. 1.2)");
+        assert_eq!(parse_header("//version: 1\n//version: 1").err().unwrap(), "malformed header on line 2: the version is declared more than once");
+        assert_eq!(parse_header("//dep: fib@1.").err().unwrap(), "malformed header on line 1: invalid version `1.` for fib@1. (expected numbers separated by `.`s, eg. 1.2)");
+    }
+
+    #[test]
+    fn imports() {
+        let specs = parse_imports("fib@1 as f {fastfib fib} util".split(' ')).unwrap();
+        assert_eq!(specs.len(), 2);
+        assert_eq!((specs[0].name.as_str(), specs[0].version.as_deref(), specs[0].alias.as_deref()), ("fib", Some("1"), Some("f")));
+        assert_eq!(specs[0].selection, Some(vec!["fastfib".to_owned(), "fib".to_owned()]));
+        assert_eq!((specs[1].name.as_str(), specs[1].alias.as_deref(), specs[1].selection.as_ref()), ("util", None, None));
+        assert_eq!(parse_imports(["fib", "{fib,fastfib}"]).unwrap()[0].selection, Some(vec!["fib".to_owned(), "fastfib".to_owned()]));
+        assert_eq!(parse_imports(["as", "f"]).err().unwrap(), "expected a file name before `as`");
+        assert_eq!(parse_imports(["fib", "as"]).err().unwrap(), "expected a module name after `fib as`");
+        assert_eq!(parse_imports(["fib", "as", "_f"]).err().unwrap(), "expected a module name after `fib as`");
+        assert_eq!(parse_imports(["fib", "{fib"]).err().unwrap(), "expected a `}` to close the selection");
+    }
+
+    #[test]
+    fn versions() {
+        assert!(version_matches("1", "1"));
+        assert!(version_matches("1", "1.2.3"));
+        assert!(version_matches("1.2", "1.2"));
+        assert!(version_matches("1.02", "1.2"));
+        assert!(!version_matches("1.2", "1"));
+        assert!(!version_matches("1.2", "1.3"));
+        assert!(!version_matches("2", "1.2"));
+        assert!(is_version("1.2.3"));
+        assert!(!is_version("1..2"));
+        assert!(!is_version(""));
+    }
+
+    #[test]
+    fn keywords() {
+        assert_eq!(strip_keyword("//dep: fib", "//dep"), Some(": fib"));
+        assert_eq!(strip_keyword("//dep", "//dep"), Some(""));
+        assert_eq!(strip_keyword("///import fib", "///import"), Some(" fib"));
+        assert_eq!(strip_keyword("//depth-first", "//dep"), None);
+        assert_eq!(strip_keyword("//versions: 1", "//version"), None);
+    }
+
+    #[test]
+    fn dependencies_come_first() {
+        let dir = fs::canonicalize(temp_dir("order")).unwrap();
+        for (name, source) in [("app", "//dep: lib\n"), ("lib", ""), ("tool", "//dep: app lib\n"), ("ping", "//dep: pong\n"), ("pong", "//dep: ping\n")] {
+            fs::write(dir.join(name).with_extension("ss"), source).unwrap();
+        }
+        let file = |name: &str| dir.join(name).with_extension("ss");
+        let files = |names: &[&str]| names.iter().map(|name| file(name)).collect::<Vec<PathBuf>>();
+        let manager = ImportManager::new(Vec::new());
+        assert_eq!(dependency_order(&manager, &files(&["lib", "app", "tool"])), files(&["lib", "app", "tool"]));
+        assert_eq!(dependency_order(&manager, &files(&["tool", "app", "lib"])), files(&["lib", "app", "tool"]));
+        // a dependency that is not in the list is left for import_file
+        assert_eq!(dependency_order(&manager, &files(&["tool", "app"])), files(&["app", "tool"]));
+        // files in a cycle keep their order
+        assert_eq!(dependency_order(&manager, &files(&["pong", "lib", "ping"])), files(&["lib", "pong", "ping"]));
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn qualified_names() {
+        use crate::parse::unmangle;
+        assert_eq!(qualified_name("util", "quad"), "util::quad");
+        assert!(qualified_name("util", "_helper").contains(' '));
+        assert_eq!(unmangle(&qualified_name("util", "_helper")), "util::_helper");
+        // a private name declared in a macro is renamed too
+        assert_eq!(unmangle(&format!("{} 12", qualified_name("util", "_i"))), "util::_i");
+        assert_eq!(unmangle(&qualified_name("util", "_2d")), "util::_2d");
+    }
+
+    #[test]
+    fn calls_wait_for_the_end_of_the_file() {
+        let mut interpreter = Interpreter::new();
+        interpreter.replace_forward_calls(ForwardCalls { in_file: true, ..ForwardCalls::default() });
+        parse_program_code("[a foo ] [b bar ] [bar ]".chars(), interpreter.access_for_parsing(), false);
+        // left for compile_file to report
+        assert_eq!(interpreter.replace_forward_calls(ForwardCalls::default()).unresolved(), ["foo"]);
+        parse_program_code("[c foo ]".chars(), interpreter.access_for_parsing(), false);
+        assert!(interpreter.replace_forward_calls(ForwardCalls::default()).unresolved().is_empty());
+    }
 }
\ No newline at end of file
@@ -1,11 +1,14 @@
-use super::{stack::Stack, io};
-use std::collections::HashMap;
+use super::{stack::Stack, io, lex::{Span, TokenKind}, effect::Annotations};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use num_bigint::{BigInt, Sign};
 
 //: program codes
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum ProgramCode {
-    Int(i64),
+    Int(Value),
     FuncCall(usize),
+    // calls whatever function the name at the index in late_calls refers to when it is run
+    LateCall(usize),
     FuncStart(Option<std::num::NonZeroUsize>),
     FuncEnd,
     PopMemLoc,
@@ -24,8 +27,14 @@ pub enum ProgramCode {
     Dup,
     Swap,
     Op(OpCode),
+    FOp(FloatOpCode),
+    FGtZero,
+    FEqZero,
+    IntToFloat,
+    FloatToInt,
 }
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum OpCode {
     NOT,
     AND,
@@ -37,23 +46,294 @@ pub enum OpCode {
     MUL,
     DIV,
     MOD,
+    SHL,
+    SHR, // arithmetic
+    USHR, // logical
+    ABS,
+    MIN,
+    MAX,
+    POW,
+    LT,
+    GT,
+    LE,
+    GE,
+    EQ,
+    NE,
+}
+impl OpCode {
+    pub fn is_unary(self) -> bool {
+        matches!(self, OpCode::NOT|OpCode::NEG|OpCode::ABS)
+    }
+}
+// operate on floats stored as the bits of an f64
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum FloatOpCode {
+    NEG,
+    ADD,
+    SUB,
+    MUL,
+    DIV,
+    MOD,
+}
+//;
+
+// the type of every value in the program codes, and of the numbers on the stack and in memory unless they are too big
+pub type Value = i64;
+
+// a number on the stack or in memory, which is only Big in the big overflow mode, when it does not fit in a Value
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Cell {
+    Int(Value),
+    Big(Box<BigInt>),
+}
+
+impl Cell {
+    // the number, or the nearest Value if it is too big, which keeps its sign
+    pub fn value(&self) -> Value {
+        match self {
+            Cell::Int(int) => *int,
+            Cell::Big(big) if big.sign() == Sign::Minus => Value::MIN,
+            Cell::Big(_) => Value::MAX,
+        }
+    }
+
+    fn to_big(&self) -> BigInt {
+        match self {
+            Cell::Int(int) => BigInt::from(*int),
+            Cell::Big(big) => (**big).clone(),
+        }
+    }
+
+    fn to_f64(&self) -> f64 {
+        match self {
+            Cell::Int(int) => *int as f64,
+            Cell::Big(big) => big.to_string().parse().unwrap_or(f64::NAN),
+        }
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Cell {
+        Cell::Int(0)
+    }
+}
+
+impl From<Value> for Cell {
+    fn from(int: Value) -> Cell {
+        Cell::Int(int)
+    }
+}
+
+impl From<BigInt> for Cell {
+    fn from(big: BigInt) -> Cell {
+        match Value::try_from(&big) {
+            Ok(int) => Cell::Int(int),
+            Err(_) => Cell::Big(Box::new(big)),
+        }
+    }
+}
+
+impl std::fmt::Display for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cell::Int(int) => write!(f, "{}", int),
+            Cell::Big(big) => write!(f, "{}", big),
+        }
+    }
+}
+
+//: arithmetic
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum OverflowMode {
+    Wrapping, // wraps around, printing a warning
+    Checked, // errors, ending the program
+    Saturating, // clamps to the nearest representable value
+    Big, // grows the number past 64 bits
+}
+
+impl OverflowMode {
+    pub fn from_name(name: &str) -> Option<OverflowMode> {
+        match name {
+            "wrap"|"wrapping" => Some(OverflowMode::Wrapping),
+            "check"|"checked" => Some(OverflowMode::Checked),
+            "sat"|"saturate"|"saturating" => Some(OverflowMode::Saturating),
+            "big"|"bignum" => Some(OverflowMode::Big),
+            _ => None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OverflowMode::Wrapping => "wrapping",
+            OverflowMode::Checked => "checked",
+            OverflowMode::Saturating => "saturating",
+            OverflowMode::Big => "big",
+        }
+    }
+}
+
+// applies op (unary ops ignore left) with the given overflow semantics,
+// returning the result and whether it overflowed or an error message
+pub fn apply_op(op: OpCode, left: Value, right: Value, mode: OverflowMode) -> Result<(Value, bool), &'static str> {
+    use OpCode::*;
+    if matches!(op, DIV|MOD) && right == 0 {
+        return Err("division by zero");
+    }
+    if matches!(op, SHL|SHR|USHR) && right < 0 {
+        return Err("cannot shift by < 0");
+    }
+    if op == POW && right < 0 {
+        return Err("cannot raise to a power < 0");
+    }
+    let bool_value = |bool: bool| if bool { -1 } else { 0 };
+    // shifting by at least the number of bits shifts out every bit
+    let shift = right.min(Value::BITS as Value) as u32;
+    let exponent = right.min(u32::MAX as Value) as u32;
+    let (wrapped, overflowed) = match op {
+        NOT => (!right, false),
+        NEG => right.overflowing_neg(),
+        AND => (left & right, false),
+        OR  => (left | right, false),
+        XOR => (left ^ right, false),
+        ADD => left.overflowing_add(right),
+        SUB => left.overflowing_sub(right),
+        MUL => left.overflowing_mul(right),
+        DIV => left.overflowing_div(right),
+        MOD => left.overflowing_rem(right),
+        SHL => (left.checked_shl(shift).unwrap_or(0), false),
+        SHR => (left.checked_shr(shift).unwrap_or(if left < 0 { -1 } else { 0 }), false),
+        USHR => (if shift == 0 { left } else { left.checked_shr(shift).map_or(0, |shifted| shifted & (Value::MAX >> (shift - 1))) }, false),
+        ABS => right.overflowing_abs(),
+        MIN => (left.min(right), false),
+        MAX => (left.max(right), false),
+        POW => left.overflowing_pow(exponent),
+        LT => (bool_value(left < right), false),
+        GT => (bool_value(left > right), false),
+        LE => (bool_value(left <= right), false),
+        GE => (bool_value(left >= right), false),
+        EQ => (bool_value(left == right), false),
+        NE => (bool_value(left != right), false),
+    };
+    if !overflowed {
+        return Ok((wrapped, false));
+    }
+    match mode {
+        // the result needs apply_big_op to fit
+        OverflowMode::Wrapping|OverflowMode::Big => Ok((wrapped, true)),
+        OverflowMode::Checked => Err("integer overflow"),
+        OverflowMode::Saturating => Ok((match op {
+            NEG => Value::MAX,
+            ADD => left.saturating_add(right),
+            SUB => left.saturating_sub(right),
+            MUL => left.saturating_mul(right),
+            DIV => left.saturating_div(right),
+            MOD => 0,
+            ABS => Value::MAX,
+            POW => left.saturating_pow(exponent),
+            _ => wrapped
+        }, true)),
+    }
+}
+
+// applies op (unary ops ignore left) without overflowing, as in the big overflow mode,
+// returning a Big result only if it does not fit in a Value
+pub fn apply_big_op(op: OpCode, left: &Cell, right: &Cell) -> Result<Cell, &'static str> {
+    use OpCode::*;
+    if let (Cell::Int(left), Cell::Int(right)) = (left, right) {
+        // shifting left drops the bits shifted out without overflowing
+        let lossless = op != SHL || *left == 0 || (0..Value::BITS as Value).contains(right) && (left << right) >> right == *left;
+        match apply_op(op, *left, *right, OverflowMode::Big)? {
+            (int, false) if lossless => return Ok(Cell::Int(int)),
+            _ => {}
+        }
+    }
+    let (left, right) = (left.to_big(), right.to_big());
+    let bool_value = |bool: bool| BigInt::from(if bool { -1 } else { 0 });
+    if matches!(op, DIV|MOD) && right.sign() == Sign::NoSign {
+        return Err("division by zero");
+    }
+    if matches!(op, SHL|SHR|USHR) && right.sign() == Sign::Minus {
+        return Err("cannot shift by < 0");
+    }
+    if op == POW && right.sign() == Sign::Minus {
+        return Err("cannot raise to a power < 0");
+    }
+    let shift = || usize::try_from(&right).map_err(|_| "cannot shift by that much");
+    Ok(Cell::from(match op {
+        NOT => !right,
+        NEG => -right,
+        AND => left & right,
+        OR  => left | right,
+        XOR => left ^ right,
+        ADD => left + right,
+        SUB => left - right,
+        MUL => left * right,
+        DIV => left / right,
+        MOD => left % right,
+        SHL => left << shift()?,
+        SHR => left >> shift()?,
+        // a logical shift needs to know how many bits the number has
+        USHR if left.sign() == Sign::Minus => return Err("cannot shift a big number < 0 logically"),
+        USHR => left >> shift()?,
+        ABS if right.sign() == Sign::Minus => -right,
+        ABS => right,
+        MIN => left.min(right),
+        MAX => left.max(right),
+        POW => left.pow(u32::try_from(&right).map_err(|_| "cannot raise to that big a power")?),
+        LT => bool_value(left < right),
+        GT => bool_value(left > right),
+        LE => bool_value(left <= right),
+        GE => bool_value(left >= right),
+        EQ => bool_value(left == right),
+        NE => bool_value(left != right),
+    }))
+}
+
+pub fn to_float(value: Value) -> f64 {
+    f64::from_bits(value as u64)
+}
+
+pub fn from_float(float: f64) -> Value {
+    float.to_bits() as Value
+}
+
+// applies op (NEG ignores left) to the floats stored in left and right
+pub fn apply_float_op(op: FloatOpCode, left: Value, right: Value) -> Value {
+    use FloatOpCode::*;
+    let (left, right) = (to_float(left), to_float(right));
+    from_float(match op {
+        NEG => -right,
+        ADD => left + right,
+        SUB => left - right,
+        MUL => left * right,
+        DIV => left / right,
+        MOD => left % right,
+    })
 }
 //;
 
-#[derive(/* Copy,  */Clone)]
+#[allow(clippy::enum_variant_names)]
+#[derive(/* Copy,  */Clone, PartialEq)]
 pub enum Variable {
     Variable(usize),
     Function(usize),
-    Macro(Vec<String>)
+    // the words it expands to and the names of its parameters
+    Macro(Vec<TokenKind>, Vec<String>),
+    // the numbers its value evaluated to when it was defined, eg. 7 for `:x;3 4 +`
+    Constant(Vec<Value>),
 }
 
-pub const MEMORY_SIZE: usize = 1000;
-pub const HEAP_START: usize = 500;
+// the names, parameters and words of a macro being built, and how many functions and macros are open in it
+pub type MacroCode = (Vec<String>, Vec<String>, Vec<TokenKind>, u8);
+
+pub const DEFAULT_MEMORY_SIZE: usize = 1000;
+pub const DEFAULT_HEAP_START: usize = 500;
 
 //: interpreter variables
 pub struct Interpreter {
     // imaginary actual stack
-    pub data_stack: Stack<i64>,
+    pub data_stack: Stack<Cell>,
 
     // imaginary call stack, probably just before arbitrary memory
     call_stack: Stack<usize>,
@@ -66,37 +346,72 @@ pub struct Interpreter {
     pc: usize,
 
     // imaginary extra memory before the stack
-    pub memory: [i64; MEMORY_SIZE],
+    pub memory: Vec<Cell>,
+    pub heap_start: usize, // variables are stored before the heap
     mem_loc: usize, // 0 is std in/out
     pub heap_pointer: usize,
     pub heap_free_pointer: usize,
+    // only in interpreter:
+    // start and length of each allocated heap block
+    pub heap_blocks: BTreeMap<usize, usize>,
+    // addresses that pause execution when written to
+    pub watchpoints: HashSet<usize>,
 
     control_flow: ControlFlow,
+    pub overflow_mode: OverflowMode,
 
     // only in interpreter:
     // names for memory locations
     variables: HashMap<String, Variable>,
     var_pointer: usize,
     // macros being built
-    macro_codes: Vec<(Vec<String>, Vec<String>, u8)>,
+    macro_codes: Vec<MacroCode>,
+    // the number of macros expanded, which numbers the names declared by each expansion
+    macro_expansions: usize,
+    // names defined while compiling a module
+    definitions: Option<Vec<(String, Option<Variable>)>>,
+    // the words parsed while expanding
+    expansion: Option<Vec<ExpandedWord>>,
+    // the stack effects of functions and the types of variables, for checking functions
+    annotations: Annotations,
+    // the name called by each late bound call
+    late_calls: Vec<String>,
+    forward_calls: ForwardCalls,
+    // blocks being parsed, innermost last
+    blocks: Vec<Block>,
 }
 //;
 
 //: interpreter methods
 impl Interpreter {
     pub fn new() -> Interpreter {
+        Interpreter::with_memory(DEFAULT_MEMORY_SIZE, DEFAULT_HEAP_START)
+    }
+
+    pub fn with_memory(memory_size: usize, heap_start: usize) -> Interpreter {
         Interpreter {
-            memory: [0; MEMORY_SIZE],
+            memory: vec![Cell::default(); memory_size],
+            heap_start,
             call_stack: Stack::new(),
             data_stack: Stack::new(),
             program_codes: Vec::new(),
             pc: 0,
             macro_codes: Vec::new(),
+            macro_expansions: 0,
+            definitions: None,
+            expansion: None,
+            annotations: Annotations::default(),
+            late_calls: Vec::new(),
+            forward_calls: ForwardCalls::default(),
+            blocks: Vec::new(),
             mem_loc: 0, // 0 is std in/out
             var_pointer: 1, //0;
-            heap_pointer: HEAP_START,
+            heap_pointer: heap_start,
             heap_free_pointer: 0,
+            heap_blocks: BTreeMap::new(),
+            watchpoints: HashSet::new(),
             control_flow: ControlFlow { func_depth: 0, if_depth: 0, if_succeeded: false, if_else: false },
+            overflow_mode: OverflowMode::Wrapping,
             variables: HashMap::new(),
         }
     }
@@ -105,14 +420,197 @@ impl Interpreter {
         self.pc >= self.program_codes.len()
     }
 
-    pub fn access_for_parsing(&mut self) -> super::parse::ParserIn {
+    // (name, address) of every named variable, ordered by address
+    pub fn named_variables(&self) -> Vec<(&str, usize)> {
+        let mut named: Vec<(&str, usize)> = self.variables.iter()
+            .filter_map(|(name, var)| if let Variable::Variable(addr) = var { Some((name.as_str(), *addr)) } else { None })
+            .collect();
+        named.sort_by_key(|(name, addr)| (*addr, *name));
+        named
+    }
+
+    pub fn variable_address(&self, name: &str) -> Option<usize> {
+        if let Some(Variable::Variable(addr)) = self.variables.get(name) {
+            Some(*addr)
+        } else {
+            None
+        }
+    }
+
+    // the code of the function at loc, from its FuncStart to its FuncEnd
+    pub fn function_code(&self, loc: usize) -> &[ProgramCode] {
+        let mut depth: usize = 0;
+        for (i, code) in self.program_codes.iter().enumerate().skip(loc + 1) {
+            match code {
+                ProgramCode::FuncStart(_) => depth += 1,
+                ProgramCode::FuncEnd if depth == 0 => return &self.program_codes[loc..=i],
+                ProgramCode::FuncEnd => depth -= 1,
+                _ => {}
+            }
+        }
+        &self.program_codes[loc.min(self.program_codes.len())..]
+    }
+
+    // removes the code of functions that can no longer be called and the top level code that has already run,
+    // returning the number of codes removed
+    pub fn compact(&mut self) -> Result<usize, &'static str> {
+        use ProgramCode::*;
+        if !self.blocks.is_empty() || self.program_codes.iter().any(|code| matches!(code, FuncStart(None) | If(None) | ElseIf(None))) {
+            return Result::Err("a function, if statement or loop is unfinished");
+        }
+        if !self.input_required() || !self.call_stack.is_empty() || self.control_flow.func_depth > 0 || self.control_flow.if_depth > 0 {
+            return Result::Err("the program is still running");
+        }
+        // every function that can be called, directly or by another function that can be
+        let mut keep: Vec<bool> = vec![false; self.program_codes.len()];
+        let mut functions: Vec<usize> = self.variables.values().filter_map(|value| if let Variable::Function(loc) = value { Some(*loc) } else { None }).collect();
+        while let Some(loc) = functions.pop() {
+            if keep.get(loc).is_none_or(|kept| *kept) {
+                continue;
+            }
+            for (i, code) in self.function_code(loc).iter().enumerate() {
+                keep[loc + i] = true;
+                if let FuncCall(target) = code {
+                    functions.push(*target);
+                }
+            }
+        }
+        // a loop without an end yet would lose its start
+        let loop_ends: HashSet<usize> = self.program_codes.iter().filter_map(|code| if let EndLoop(loc) = code { Some(*loc) } else { None }).collect();
+        if self.program_codes.iter().enumerate().any(|(i, code)| !keep[i] && matches!(code, Loop) && !loop_ends.contains(&i)) {
+            return Result::Err("a loop is unfinished");
+        }
+
+        // the new location of each code
+        let mut new_locs: Vec<usize> = Vec::with_capacity(keep.len());
+        let mut kept: usize = 0;
+        for keep in &keep {
+            new_locs.push(kept);
+            kept += *keep as usize;
+        }
+        let relocate = |loc: usize| new_locs.get(loc).copied().unwrap_or(kept);
+        let relocate_nonzero = |loc: std::num::NonZeroUsize| std::num::NonZeroUsize::new(relocate(loc.get()));
+        let removed = self.program_codes.len() - kept;
+        let mut i: usize = 0;
+        self.program_codes.retain(|_| {
+            i += 1;
+            keep[i - 1]
+        });
+        // the names of the late bound calls that are kept, renumbered in the order they are used
+        let mut late_calls: Vec<String> = Vec::new();
+        let mut late_call_indices: HashMap<usize, usize> = HashMap::new();
+        for code in self.program_codes.iter_mut() {
+            *code = match *code {
+                FuncCall(loc) => FuncCall(relocate(loc)),
+                LateCall(index) => LateCall(*late_call_indices.entry(index).or_insert_with(|| {
+                    late_calls.push(self.late_calls[index].clone());
+                    late_calls.len() - 1
+                })),
+                FuncStart(Some(loc)) => FuncStart(relocate_nonzero(loc)),
+                If(Some(loc)) => If(relocate_nonzero(loc)),
+                ElseIf(Some(loc)) => ElseIf(relocate_nonzero(loc)),
+                EndLoop(loc) => EndLoop(relocate(loc)),
+                code => code,
+            };
+        }
+        for value in self.variables.values_mut() {
+            if let Variable::Function(loc) = value {
+                *loc = relocate(*loc);
+            }
+        }
+        self.late_calls = late_calls;
+        self.annotations.relocate(&keep, relocate);
+        self.forward_calls.calls.retain_mut(|(_, loc)| {
+            let kept = keep[*loc];
+            *loc = relocate(*loc);
+            kept
+        });
+        self.pc = self.program_codes.len();
+        Ok(removed)
+    }
+
+    pub fn program_len(&self) -> usize {
+        self.program_codes.len()
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.variables.contains_key(name)
+    }
+
+    pub fn variables_mut(&mut self) -> &mut HashMap<String, Variable> {
+        &mut self.variables
+    }
+
+    // starts recording the names defined by everything parsed until stop_recording_definitions
+    pub fn record_definitions(&mut self) {
+        self.definitions = Some(Vec::new());
+    }
+
+    pub fn stop_recording_definitions(&mut self) -> Vec<(String, Option<Variable>)> {
+        self.definitions.take().unwrap_or_default()
+    }
+
+    pub fn late_calls(&self) -> &[String] {
+        &self.late_calls
+    }
+
+    pub fn late_calls_mut(&mut self) -> &mut [String] {
+        &mut self.late_calls
+    }
+
+    // swaps the calls waiting for functions to be defined, so that a file's calls are resolved separately
+    pub fn replace_forward_calls(&mut self, forward_calls: ForwardCalls) -> ForwardCalls {
+        std::mem::replace(&mut self.forward_calls, forward_calls)
+    }
+
+    // swaps the blocks being parsed, so that a file's blocks are checked separately
+    pub fn replace_blocks(&mut self, blocks: Vec<Block>) -> Vec<Block> {
+        std::mem::replace(&mut self.blocks, blocks)
+    }
+
+    pub fn blocks_mut(&mut self) -> &mut Vec<Block> {
+        &mut self.blocks
+    }
+
+    // compiles code without keeping anything it compiles or defines, returning each word it expanded to and the codes it compiled to
+    pub fn expand(&mut self, source: &str, compiler_optimise: bool) -> (Vec<ExpandedWord>, Vec<ProgramCode>) {
+        let program_codes = self.program_codes.clone();
+        let variables = self.variables.clone();
+        let macro_codes = self.macro_codes.clone();
+        let forward_calls = self.forward_calls.clone();
+        let blocks = self.blocks.clone();
+        let annotations = self.annotations.clone();
+        let (var_pointer, late_calls_len) = (self.var_pointer, self.late_calls.len());
+        self.expansion = Some(Vec::new());
+        super::parse::parse_program_code(source.chars(), self.access_for_parsing(), compiler_optimise);
+        let expansion = self.expansion.take().unwrap_or_default();
+        let compiled = std::mem::replace(&mut self.program_codes, program_codes);
+        self.variables = variables;
+        self.macro_codes = macro_codes;
+        self.forward_calls = forward_calls;
+        self.blocks = blocks;
+        self.annotations = annotations;
+        self.var_pointer = var_pointer;
+        self.late_calls.truncate(late_calls_len);
+        (expansion, compiled)
+    }
+
+    pub fn access_for_parsing(&mut self) -> super::parse::ParserIn<'_> {
         super::parse::ParserIn {
             program_codes: &mut self.program_codes,
             macro_codes: &mut self.macro_codes,
+            macro_expansions: &mut self.macro_expansions,
             variables: &mut self.variables,
-            memory: &mut self.memory,
+            memory: &mut self.memory[..self.heap_start],
             var_pointer: &mut self.var_pointer,
-            pc: self.pc
+            pc: self.pc,
+            overflow_mode: self.overflow_mode,
+            definitions: &mut self.definitions,
+            expansion: &mut self.expansion,
+            annotations: &mut self.annotations,
+            late_calls: &mut self.late_calls,
+            forward_calls: &mut self.forward_calls,
+            blocks: &mut self.blocks,
         }
     }
 
@@ -121,17 +619,76 @@ impl Interpreter {
             &mut self.data_stack,
             &mut self.call_stack,
             &mut self.program_codes, &mut self.pc,
-            &mut self.memory, &mut self.mem_loc,
-            &mut self.heap_pointer, &mut self.heap_free_pointer,
-            &mut self.control_flow)
+            &mut self.memory, self.heap_start, &mut self.mem_loc,
+            &mut self.heap_pointer, &mut self.heap_free_pointer, &mut self.heap_blocks,
+            &self.watchpoints,
+            &self.variables, &self.late_calls,
+            &mut self.control_flow, self.overflow_mode)
     }
 }
 //;
 
 //: extra structs
+// calls to functions that have not been defined yet
+#[derive(Default, Clone)]
+pub struct ForwardCalls {
+    // (name, location) of each call, which is compiled as a late bound call until the function is defined
+    pub calls: Vec<(String, usize)>,
+    // names declared with `[name]`, whose calls are not reported until the end of the file
+    pub declared: Vec<String>,
+    // whether the calls are in a file being imported, which reports the ones still not defined at its end instead of at the end of each function
+    pub in_file: bool,
+}
+impl ForwardCalls {
+    // the names that are still called or declared but not defined
+    pub fn unresolved(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.calls.iter().map(|(name, _)| name.as_str()).chain(self.declared.iter().map(String::as_str)).collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum BlockKind {
+    Func,
+    If,
+    // after a `)!`, waiting for the `(` that follows it
+    Else,
+    Loop,
+}
+impl BlockKind {
+    pub fn opener(self) -> &'static str {
+        match self {
+            BlockKind::Func => "[",
+            BlockKind::If => "(",
+            BlockKind::Else => ")!",
+            BlockKind::Loop => "{",
+        }
+    }
+}
+
+// a block that has been opened but not closed yet, with the location of the code it opened with
+#[derive(Copy, Clone)]
+pub struct Block {
+    pub kind: BlockKind,
+    pub loc: usize,
+    pub span: Span,
+}
+
+// a word after macros have been expanded, along with how many macros deep it was expanded from and the codes it compiled to,
+// which can start before the codes of the words before it have ended if it was optimised together with them
+pub struct ExpandedWord {
+    pub word: String,
+    pub depth: usize,
+    pub codes: std::ops::Range<usize>,
+}
+
 pub struct InterpreterOut {
     pub printed: bool,
     pub err: bool,
+    // (address, previous value) of a watched address that was written to
+    pub watched: Option<(usize, Cell)>,
 }
 
 pub struct ControlFlow {
@@ -143,30 +700,41 @@ pub struct ControlFlow {
 //;
 
 //: interpret
+#[allow(clippy::too_many_arguments)]
 fn interpret(
-    data_stack: &mut Stack<i64>,
+    data_stack: &mut Stack<Cell>,
     call_stack: &mut Stack<usize>,
-    program_codes: &mut Vec<ProgramCode>,
+    program_codes: &mut [ProgramCode],
     pc: &mut usize,
-    memory: &mut [i64; MEMORY_SIZE],
+    memory: &mut [Cell],
+    heap_start: usize,
     mem_loc: &mut usize,
     heap_pointer: &mut usize,
     heap_free_pointer: &mut usize,
-    control_flow: &mut ControlFlow) -> InterpreterOut {
-    use OpCode::*;
+    heap_blocks: &mut BTreeMap<usize, usize>,
+    watchpoints: &HashSet<usize>,
+    variables: &HashMap<String, Variable>,
+    late_calls: &[String],
+    control_flow: &mut ControlFlow,
+    overflow_mode: OverflowMode) -> InterpreterOut {
     use ProgramCode::*;
     use super::print_err;
     let ControlFlow { func_depth, if_depth, if_succeeded, if_else } = control_flow;
     macro_rules! ignore {
         () => { *func_depth != 0 || *if_depth != 0 };
     }
+    // pops a number used as an address, length or amount, which cannot be big
+    macro_rules! pop_value {
+        () => { data_stack.try_pop().as_ref().map(Cell::value) };
+    }
     let mut newline: bool = true;
     let mut err: bool = false;
+    let mut watched: Option<(usize, Cell)> = None;
     while *pc < program_codes.len() {
         match program_codes[*pc] {
             Int(int) => {
                 if !ignore!() {
-                    data_stack.push(int)
+                    data_stack.push(Cell::Int(int))
                 }
             }
             FuncCall(loc) => {
@@ -175,6 +743,26 @@ fn interpret(
                     *pc = loc;
                 }
             }
+            LateCall(index) => {
+                if !ignore!() {
+                    match variables.get(&late_calls[index]) {
+                        Some(Variable::Function(loc)) => {
+                            call_stack.push(*pc);
+                            *pc = *loc;
+                        }
+                        Some(_) => {
+                            print_err(format!("@{} is not a function", late_calls[index]));
+                            err = true;
+                            break;
+                        }
+                        None => {
+                            print_err(format!("@{} is not defined", late_calls[index]));
+                            err = true;
+                            break;
+                        }
+                    }
+                }
+            }
             FuncStart(op_loc) => {
                 if let Some(loc) = op_loc {
                     *pc = loc.into();
@@ -193,7 +781,7 @@ fn interpret(
             }
             PopMemLoc => {
                 if !ignore!() {
-                    if let Some(int) = data_stack.try_pop() {
+                    if let Some(int) = pop_value!() {
                         if int >= 0 {
                             *mem_loc = int as usize;
                         } else {
@@ -216,11 +804,11 @@ fn interpret(
                         }
                         data_stack.push(-1);
                         for chr in input.trim_end_matches(['\n', '\r']).chars().rev() {
-                            data_stack.push(chr as i64);
+                            data_stack.push(chr as Value);
                         } */
-                        data_stack.push(io::read_char() as i64);
+                        data_stack.push(Cell::Int(io::read_char() as Value));
                     } else {
-                        data_stack.push(memory[*mem_loc]);
+                        data_stack.push(memory[*mem_loc].clone());
                     }
                 }
             }
@@ -228,14 +816,18 @@ fn interpret(
                 if !ignore!() {
                     if let Some(int) = data_stack.try_pop() {
                         if *mem_loc == 0 {
-                            if int < 0 {
+                            if int.value() < 0 {
                                 print_err("char cannot be < 0")
-                            } else {
-                                let chr = char::from_u32(int as u32).unwrap();
+                            } else if let Some(chr) = u32::try_from(int.value()).ok().and_then(char::from_u32) {
                                 io::print_flushed(chr);
                                 newline = chr == '\n';
+                            } else {
+                                print_err(format!("{} is not a char", int))
                             }
                         } else {
+                            if watchpoints.contains(mem_loc) {
+                                watched = Some((*mem_loc, memory[*mem_loc].clone()));
+                            }
                             memory[*mem_loc] = int;
                         }
                     }
@@ -243,7 +835,7 @@ fn interpret(
             }
             Err => {
                 if !ignore!() {
-                    print_err(format!("\n"));
+                    print_err("\n");
                     err = true;
                     break;
                 }
@@ -251,7 +843,7 @@ fn interpret(
             If(op_loc) => {
                 if *func_depth == 0 {
                     if *if_depth == 0 && !*if_succeeded {
-                        if let Some(0) = data_stack.try_pop() {
+                        if let Some(0) = pop_value!() {
                             if let Some(loc) = op_loc {
                                 *pc = loc.into();
                             } else {
@@ -314,61 +906,64 @@ fn interpret(
             }
             GtZero => {
                 if !ignore!() {
-                    if let Some(value) = data_stack.try_pop() {
+                    if let Some(value) = pop_value!() {
                         if value > 0 {
-                            data_stack.push(-1)
+                            data_stack.push(Cell::Int(-1))
                         } else {
-                            data_stack.push(0)
+                            data_stack.push(Cell::Int(0))
                         }
                     }
                 }
             }
             EqZero => {
                 if !ignore!() {
-                    if let Some(value) = data_stack.try_pop() {
+                    if let Some(value) = pop_value!() {
                         if value == 0 {
-                            data_stack.push(-1)
+                            data_stack.push(Cell::Int(-1))
                         } else {
-                            data_stack.push(0)
+                            data_stack.push(Cell::Int(0))
                         }
                     }
                 }
             }
             HeapAlloc => {
                 if !ignore!() {
-                    if let Some(length) = data_stack.try_pop() {
+                    if let Some(length) = pop_value!() {
                         if length < 2 {
                             print_err("cannot allocate under 2 cells to heap");
                         } else {
                             let mut prev_mem_loc: usize = 0;
                             let mut mem_loc: usize = *heap_free_pointer;
                             while mem_loc != 0 {
-                                match memory[mem_loc + 1] - length {
+                                match memory[mem_loc + 1].value().saturating_sub(length) {
                                     0 => {
-                                        data_stack.push(mem_loc as i64);
+                                        data_stack.push(Cell::Int(mem_loc as Value));
+                                        heap_blocks.insert(mem_loc, length as usize);
                                         if prev_mem_loc == 0 {
                                             *heap_free_pointer = 0;
                                         } else {
-                                            memory[prev_mem_loc] = memory[mem_loc];
+                                            memory[prev_mem_loc] = memory[mem_loc].clone();
                                         }
                                         break;
                                     }
                                     2.. => {
-                                        data_stack.push(mem_loc as i64);
-                                        memory[prev_mem_loc] = mem_loc as i64 + length;
+                                        data_stack.push(Cell::Int(mem_loc as Value));
+                                        heap_blocks.insert(mem_loc, length as usize);
+                                        memory[prev_mem_loc] = Cell::Int(mem_loc as Value + length);
                                         break;
                                     }
                                     _ => {
                                         prev_mem_loc = mem_loc;
-                                        mem_loc = memory[mem_loc] as usize;
+                                        mem_loc = memory[mem_loc].value() as usize;
                                     }
                                 }
                             }
                             if mem_loc == 0 {
-                                if *heap_pointer + length as usize > memory.len() + 1 {
-                                    print_err(format!("!? wouldn't fit in the heap :/ (only {} / {} cells left)", MEMORY_SIZE - *heap_pointer, MEMORY_SIZE - HEAP_START));
+                                if *heap_pointer + length as usize > memory.len() {
+                                    print_err(format!("!? wouldn't fit in the heap :/ (only {} / {} cells left)", memory.len() - *heap_pointer, memory.len() - heap_start));
                                 } else {
-                                    data_stack.push(*heap_pointer as i64);
+                                    data_stack.push(Cell::Int(*heap_pointer as Value));
+                                    heap_blocks.insert(*heap_pointer, length as usize);
                                     *heap_pointer += length as usize;
                                 }
                             }
@@ -378,13 +973,13 @@ fn interpret(
             }
             HeapFree => {
                 if !ignore!() {
-                    if let Some(addr) = data_stack.try_pop() {
-                        if addr < HEAP_START as i64 {
+                    if let Some(addr) = pop_value!() {
+                        if addr < heap_start as Value {
                             print_err("cannot free outside of heap");
-                        } else if let Some(length) = data_stack.try_pop() {
+                        } else if let Some(length) = pop_value!() {
                             if length < 2 {
                                 print_err("cannot free under 2 cells from heap");
-                            } else if addr + length > *heap_pointer as i64 {
+                            } else if addr.saturating_add(length) > *heap_pointer as Value {
                                 print_err("cannot free unallocated memory from heap");
                             } else {
                                 // all in format ([location of pointer to current], [location of current])
@@ -393,8 +988,8 @@ fn interpret(
                                 let mut after_mem_loc: (usize, usize) = (0, 0);
                                 let mut err: bool = false;
                                 while mem_loc.1 != 0 {
-                                    match (mem_loc.1 as i64 + memory[mem_loc.1 + 1] - addr/* amount mem-end is after start */,
-                                        addr + length - mem_loc.1 as i64/* amount mem-start is before end */) {
+                                    match (mem_loc.1 as Value + memory[mem_loc.1 + 1].value() - addr/* amount mem-end is after start */,
+                                        addr + length - mem_loc.1 as Value/* amount mem-start is before end */) {
                                         (0, _) => { // mem directly before
                                             before_mem_loc = mem_loc;
                                         }
@@ -412,47 +1007,49 @@ fn interpret(
                                         break;
                                     }
                                     mem_loc.0 = mem_loc.1;
-                                    mem_loc.1 = memory[mem_loc.0] as usize; // mem_loc now points to nect in free_heap list
+                                    mem_loc.1 = memory[mem_loc.0].value() as usize; // mem_loc now points to nect in free_heap list
                                 }
                                 if !err {
                                     let addr: usize = addr as usize; // addr and length now usize as have been proved to be positive
                                     let length: usize = length as usize;
+                                    untrack_heap_block(heap_blocks, addr, length);
                                     macro_rules! reassign_pointer {
                                         ($previous:expr, $next:expr) => {
+                                            let next: Value = $next;
                                             if $previous == 0 {
-                                                *heap_free_pointer = $next as usize;
+                                                *heap_free_pointer = next as usize;
                                             } else {
-                                                memory[$previous] = $next;
+                                                memory[$previous] = Cell::Int(next);
                                             }
                                         };
                                     }
                                     if after_mem_loc != (0, 0) { // mem after
                                         if before_mem_loc != (0, 0) { // mems both before and after
                                             // remove mem after from free_heap list
-                                            reassign_pointer!(after_mem_loc.0, memory[after_mem_loc.1]);
+                                            reassign_pointer!(after_mem_loc.0, memory[after_mem_loc.1].value());
                                             // extend mem before length to cover current and mem after
-                                            memory[before_mem_loc.1 + 1] += memory[after_mem_loc.1 + 1] + length as i64;
+                                            memory[before_mem_loc.1 + 1] = Cell::Int(memory[before_mem_loc.1 + 1].value() + memory[after_mem_loc.1 + 1].value() + length as Value);
 
                                         } else { // mem only after
-                                            reassign_pointer!(after_mem_loc.0, addr as i64);
-                                            memory[addr] = memory[after_mem_loc.1];
-                                            memory[addr + 1] = memory[after_mem_loc.1 + 1] + length as i64;
+                                            reassign_pointer!(after_mem_loc.0, addr as Value);
+                                            memory[addr] = memory[after_mem_loc.1].clone();
+                                            memory[addr + 1] = Cell::Int(memory[after_mem_loc.1 + 1].value() + length as Value);
                                         }
                                     } else { // no mem after
                                         if before_mem_loc != (0, 0) { // mem only before
                                             if addr + length == *heap_pointer { // at end of heap
-                                                reassign_pointer!(before_mem_loc.0, memory[before_mem_loc.1]); // before_mem_loc.0 is previous in free_heap list
+                                                reassign_pointer!(before_mem_loc.0, memory[before_mem_loc.1].value()); // before_mem_loc.0 is previous in free_heap list
                                                 *heap_pointer = before_mem_loc.1;
                                             } else {
-                                                memory[before_mem_loc.1 + 1] += length as i64;
+                                                memory[before_mem_loc.1 + 1] = Cell::Int(memory[before_mem_loc.1 + 1].value() + length as Value);
                                             }
                                         } else { // no mem on either side
                                             if addr + length == *heap_pointer { // at end of heap
                                                 *heap_pointer = addr;
                                             } else {
-                                                reassign_pointer!(mem_loc.0, addr as i64); // mem_loc.0 is last in free_heap list
-                                                memory[addr] = 0;
-                                                memory[addr + 1] = length as i64;
+                                                reassign_pointer!(mem_loc.0, addr as Value); // mem_loc.0 is last in free_heap list
+                                                memory[addr] = Cell::Int(0);
+                                                memory[addr + 1] = Cell::Int(length as Value);
                                             }
                                         }
                                     }
@@ -464,11 +1061,11 @@ fn interpret(
             }
             Dup => {
                 if !ignore!() {
-                    if let Some(amount) = data_stack.try_pop() {
+                    if let Some(amount) = pop_value!() {
                         if amount > 0 {
                             // if let Some(from_top) = data_stack.try_pop() {
                                 if let Some(value) = data_stack.try_peek(0/* from_top as usize */) {
-                                    data_stack.push_multiple(std::iter::repeat(value).take(amount as usize));
+                                    data_stack.push_multiple(std::iter::repeat_n(value, amount as usize));
                                 }
                             // }
                         } else if amount == 0 {
@@ -481,7 +1078,7 @@ fn interpret(
             }
             Swap => {
                 if !ignore!() {
-                    if let Some(from_top) = data_stack.try_pop() {
+                    if let Some(from_top) = pop_value!() {
                         if from_top >= 0 {
                             if let Some(top) = data_stack.try_pop() {
                                 if let Some(middle) = data_stack.try_peek(from_top as usize) {
@@ -498,35 +1095,231 @@ fn interpret(
             Op(op) => {
                 if !ignore!() {
                     if let Some(right) = data_stack.try_pop() {
-                        if matches!(op, NOT) {
-                            data_stack.push(!right)
-                        } else if matches!(op, NEG) {
-                            data_stack.push(-right)
-                        } else if let Some(left) = data_stack.try_pop() {
-                            let overflow = |tuple: (i64, bool)| {
-                                if tuple.1 {
-                                    print_err("integer overflow");
-                                };
-                                tuple.0
+                        let left = if op.is_unary() { Some(Cell::Int(0)) } else { data_stack.try_pop() };
+                        if let Some(left) = left {
+                            let result = match overflow_mode {
+                                OverflowMode::Big => apply_big_op(op, &left, &right).map(|value| (value, false)),
+                                _ => apply_op(op, left.value(), right.value(), overflow_mode).map(|(value, overflowed)| (Cell::Int(value), overflowed)),
                             };
-                            data_stack.push(match op {
-                                AND => left & right,
-                                OR  => left | right,
-                                XOR => left ^ right,
-                                ADD => overflow(left.overflowing_add(right)),
-                                SUB => overflow(left.overflowing_sub(right)),
-                                MUL => overflow(left.overflowing_mul(right)),
-                                DIV => overflow(left.overflowing_div(right)),
-                                MOD => left % right,
-                                NOT|NEG => 0
-                            })
+                            match result {
+                                Ok((value, overflowed)) => {
+                                    if overflowed && overflow_mode == OverflowMode::Wrapping {
+                                        print_err("integer overflow");
+                                    }
+                                    data_stack.push(value)
+                                }
+                                Result::Err(error) => {
+                                    print_err(error);
+                                    err = true;
+                                    break;
+                                }
+                            }
                         }
                     }
                 }
             }
+            FOp(op) => {
+                if !ignore!() {
+                    if let Some(right) = pop_value!() {
+                        let left = if matches!(op, FloatOpCode::NEG) { Some(0) } else { pop_value!() };
+                        if let Some(left) = left {
+                            data_stack.push(Cell::Int(apply_float_op(op, left, right)));
+                        }
+                    }
+                }
+            }
+            FGtZero => {
+                if !ignore!() {
+                    if let Some(value) = pop_value!() {
+                        data_stack.push(Cell::Int(if to_float(value) > 0.0 { -1 } else { 0 }));
+                    }
+                }
+            }
+            FEqZero => {
+                if !ignore!() {
+                    if let Some(value) = pop_value!() {
+                        data_stack.push(Cell::Int(if to_float(value) == 0.0 { -1 } else { 0 }));
+                    }
+                }
+            }
+            IntToFloat => {
+                if !ignore!() {
+                    if let Some(value) = data_stack.try_pop() {
+                        data_stack.push(Cell::Int(from_float(value.to_f64())));
+                    }
+                }
+            }
+            FloatToInt => {
+                if !ignore!() {
+                    if let Some(value) = pop_value!() {
+                        data_stack.push(Cell::Int(to_float(value) as Value));
+                    }
+                }
+            }
         }
         *pc += 1;
+        if watched.is_some() {
+            break;
+        }
+    }
+    InterpreterOut { printed: newline, err, watched }
+}
+//;
+
+// removes the freed cells from the tracked blocks, keeping whatever is left either side of them
+fn untrack_heap_block(heap_blocks: &mut BTreeMap<usize, usize>, addr: usize, length: usize) {
+    let overlapping: Vec<(usize, usize)> = heap_blocks.range(..addr + length)
+        .filter(|(start, len)| **start + **len > addr)
+        .map(|(start, len)| (*start, *len))
+        .collect();
+    for (start, len) in overlapping {
+        heap_blocks.remove(&start);
+        if start < addr {
+            heap_blocks.insert(start, addr - start);
+        }
+        if start + len > addr + length {
+            heap_blocks.insert(addr + length, start + len - addr - length);
+        }
     }
-    InterpreterOut { printed: newline, err }
 }
-//;
\ No newline at end of file
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse_program_code;
+
+    // runs source, returning what it leaves on the stack
+    fn run(interpreter: &mut Interpreter, source: &str) -> Vec<Value> {
+        parse_program_code(source.chars(), interpreter.access_for_parsing(), false);
+        interpreter.interpret();
+        let mut stack: Vec<Value> = Vec::new();
+        while !interpreter.data_stack.is_empty() {
+            stack.extend(interpreter.data_stack.try_pop().as_ref().map(Cell::value));
+        }
+        stack.reverse();
+        stack
+    }
+
+    fn big(source: &str) -> Cell {
+        Cell::from(source.parse::<BigInt>().unwrap())
+    }
+
+    #[test]
+    fn wrapping_ops() {
+        use OpCode::*;
+        let mode = OverflowMode::Wrapping;
+        assert_eq!(apply_op(ADD, 2, 3, mode), Ok((5, false)));
+        assert_eq!(apply_op(ADD, Value::MAX, 1, mode), Ok((Value::MIN, true)));
+        assert_eq!(apply_op(MUL, Value::MAX, 2, mode), Ok((-2, true)));
+        assert_eq!(apply_op(NEG, 0, Value::MIN, mode), Ok((Value::MIN, true)));
+        assert_eq!(apply_op(ABS, 0, Value::MIN, mode), Ok((Value::MIN, true)));
+        assert_eq!(apply_op(ABS, 0, -5, mode), Ok((5, false)));
+        assert_eq!(apply_op(DIV, Value::MIN, -1, mode), Ok((Value::MIN, true)));
+        assert_eq!(apply_op(MOD, Value::MIN, -1, mode), Ok((0, true)));
+        assert_eq!(apply_op(POW, 2, 10, mode), Ok((1024, false)));
+        assert_eq!(apply_op(POW, 2, 64, mode), Ok((0, true)));
+        assert_eq!(apply_op(POW, 3, 0, mode), Ok((1, false)));
+        assert_eq!(apply_op(LT, 1, 2, mode), Ok((-1, false)));
+        assert_eq!(apply_op(NE, 1, 1, mode), Ok((0, false)));
+        assert_eq!(apply_op(DIV, 1, 0, mode), Result::Err("division by zero"));
+        assert_eq!(apply_op(MOD, 1, 0, mode), Result::Err("division by zero"));
+        assert_eq!(apply_op(POW, 2, -1, mode), Result::Err("cannot raise to a power < 0"));
+    }
+
+    #[test]
+    fn shifts() {
+        use OpCode::*;
+        for mode in [OverflowMode::Wrapping, OverflowMode::Checked, OverflowMode::Saturating] {
+            assert_eq!(apply_op(SHL, 1, 3, mode), Ok((8, false)));
+            // shifting out every bit leaves nothing, or the sign for an arithmetic shift, without overflowing
+            assert_eq!(apply_op(SHL, 1, 63, mode), Ok((Value::MIN, false)));
+            assert_eq!(apply_op(SHL, 1, 64, mode), Ok((0, false)));
+            assert_eq!(apply_op(SHL, 1, Value::MAX, mode), Ok((0, false)));
+            assert_eq!(apply_op(SHR, -16, 2, mode), Ok((-4, false)));
+            assert_eq!(apply_op(SHR, -16, 100, mode), Ok((-1, false)));
+            assert_eq!(apply_op(SHR, 16, 100, mode), Ok((0, false)));
+            assert_eq!(apply_op(USHR, -1, 60, mode), Ok((15, false)));
+            assert_eq!(apply_op(USHR, -1, 0, mode), Ok((-1, false)));
+            assert_eq!(apply_op(USHR, -1, 64, mode), Ok((0, false)));
+            assert_eq!(apply_op(SHL, 1, -1, mode), Result::Err("cannot shift by < 0"));
+            assert_eq!(apply_op(USHR, 1, -1, mode), Result::Err("cannot shift by < 0"));
+        }
+    }
+
+    #[test]
+    fn checked_ops() {
+        use OpCode::*;
+        let mode = OverflowMode::Checked;
+        assert_eq!(apply_op(SUB, 5, 7, mode), Ok((-2, false)));
+        assert_eq!(apply_op(SUB, Value::MIN, 1, mode), Result::Err("integer overflow"));
+        assert_eq!(apply_op(NEG, 0, Value::MIN, mode), Result::Err("integer overflow"));
+        assert_eq!(apply_op(ABS, 0, Value::MIN, mode), Result::Err("integer overflow"));
+        assert_eq!(apply_op(POW, 10, 19, mode), Result::Err("integer overflow"));
+        assert_eq!(apply_op(POW, 10, 18, mode), Ok((1_000_000_000_000_000_000, false)));
+        assert_eq!(apply_op(DIV, Value::MIN, -1, mode), Result::Err("integer overflow"));
+    }
+
+    #[test]
+    fn saturating_ops() {
+        use OpCode::*;
+        let mode = OverflowMode::Saturating;
+        assert_eq!(apply_op(ADD, Value::MAX, 1, mode), Ok((Value::MAX, true)));
+        assert_eq!(apply_op(SUB, Value::MIN, 1, mode), Ok((Value::MIN, true)));
+        assert_eq!(apply_op(MUL, Value::MIN, 2, mode), Ok((Value::MIN, true)));
+        assert_eq!(apply_op(MUL, Value::MIN, -1, mode), Ok((Value::MAX, true)));
+        assert_eq!(apply_op(NEG, 0, Value::MIN, mode), Ok((Value::MAX, true)));
+        assert_eq!(apply_op(ABS, 0, Value::MIN, mode), Ok((Value::MAX, true)));
+        assert_eq!(apply_op(DIV, Value::MIN, -1, mode), Ok((Value::MAX, true)));
+        assert_eq!(apply_op(MOD, Value::MIN, -1, mode), Ok((0, true)));
+        assert_eq!(apply_op(POW, 2, 64, mode), Ok((Value::MAX, true)));
+        assert_eq!(apply_op(POW, -2, 65, mode), Ok((Value::MIN, true)));
+        assert_eq!(apply_op(ADD, 1, 2, mode), Ok((3, false)));
+    }
+
+    #[test]
+    fn big_ops() {
+        use OpCode::*;
+        let op = |op: OpCode, left: Cell, right: Cell| apply_big_op(op, &left, &right);
+        assert_eq!(op(ADD, Cell::Int(Value::MAX), Cell::Int(1)), Ok(big("9223372036854775808")));
+        assert_eq!(op(POW, Cell::Int(2), Cell::Int(100)), Ok(big("1267650600228229401496703205376")));
+        assert_eq!(op(NEG, Cell::Int(0), Cell::Int(Value::MIN)), Ok(big("9223372036854775808")));
+        assert_eq!(op(ABS, Cell::Int(0), big("-100000000000000000000")), Ok(big("100000000000000000000")));
+        // results that fit are not big
+        assert_eq!(op(SUB, big("9223372036854775808"), Cell::Int(1)), Ok(Cell::Int(Value::MAX)));
+        assert_eq!(op(DIV, big("-100000000000000000000"), big("100000000000000000000")), Ok(Cell::Int(-1)));
+        assert_eq!(op(LT, big("-100000000000000000000"), Cell::Int(0)), Ok(Cell::Int(-1)));
+        // shifting left keeps the bits shifted past 64
+        assert_eq!(op(SHL, Cell::Int(3), Cell::Int(63)), Ok(big("27670116110564327424")));
+        assert_eq!(op(SHL, Cell::Int(-1), Cell::Int(64)), Ok(big("-18446744073709551616")));
+        assert_eq!(op(SHL, Cell::Int(1), Cell::Int(62)), Ok(Cell::Int(1 << 62)));
+        assert_eq!(op(SHR, big("-18446744073709551616"), Cell::Int(60)), Ok(Cell::Int(-16)));
+        assert_eq!(op(USHR, Cell::Int(-1), Cell::Int(60)), Ok(Cell::Int(15)));
+        assert_eq!(op(USHR, big("-18446744073709551616"), Cell::Int(1)), Result::Err("cannot shift a big number < 0 logically"));
+        assert_eq!(op(DIV, big("18446744073709551616"), Cell::Int(0)), Result::Err("division by zero"));
+        assert_eq!(op(SHL, Cell::Int(1), Cell::Int(-1)), Result::Err("cannot shift by < 0"));
+        assert_eq!(op(POW, big("18446744073709551616"), big("18446744073709551616")), Result::Err("cannot raise to that big a power"));
+        assert_eq!(big("-100000000000000000000").value(), Value::MIN);
+        assert_eq!(big("100000000000000000000").to_string(), "100000000000000000000");
+    }
+
+    #[test]
+    fn compacting() {
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, "[f 1 ] [f 2 ] :total");
+        run(&mut interpreter, "[sign /*: int -- int */ 1 >> 0 >? ( -1 >> 1 )! -1 ( -1 >> 0 ) ]");
+        run(&mut interpreter, "[sum { 1 >> total . < + total . > 1 - 1 >> ( } ) -1 >> ]");
+        run(&mut interpreter, "[uses_f f sign @later ] [later 7 ]");
+        for _ in 0..10 {
+            run(&mut interpreter, "[temp @f ]");
+        }
+        assert_eq!(interpreter.late_calls.len(), 11);
+        assert!(interpreter.compact().unwrap() > 0);
+        // compacting again has nothing left to remove
+        assert_eq!(interpreter.compact(), Ok(0));
+        // only the late bound calls still in the code are kept
+        assert_eq!(interpreter.late_calls, ["later", "f"]);
+        assert_eq!(run(&mut interpreter, "5 sum total . < -3 sign 4 sign uses_f temp"), [15, 0, 1, 1, 7, 2]);
+        let Some(Variable::Function(sign)) = interpreter.variables.get("sign") else { panic!("sign is not a function") };
+        assert!(interpreter.annotations.effects.contains_key(sign));
+    }
+}
\ No newline at end of file
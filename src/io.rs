@@ -10,12 +10,12 @@ pub fn read_char() -> char {
             Err(_) => '\n'
         }
     } else {
-        Term::stdout().read_char().unwrap_or(char::default())
+        Term::stdout().read_char().unwrap_or_default()
     }
 }
 #[cfg(not(debug_assertions))]
 pub fn read_char() -> char {
-    Term::stdout().read_char().unwrap_or(char::default())
+    Term::stdout().read_char().unwrap_or_default()
 }
 
 #[cfg(debug_assertions)]
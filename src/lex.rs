@@ -0,0 +1,430 @@
+use super::interpret::Value;
+
+// where a token is in the source, as char offsets, along with the line and column it starts on (counting from 1)
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum TokenKind {
+    Word(String),
+    Int(Value),
+    // the contents of a string, with any escapes left in, and the quote it is bounded by
+    Str(String, char),
+    // a string prefixed with `#`, which has its length pushed after it
+    LenStr(String, char),
+    // the text of a comment, without the `//` or `/* */`
+    Comment(String),
+    // the text of a `/*: a b -- c */` stack effect annotation, without the `/*:` or `*/`
+    Effect(String),
+}
+
+impl TokenKind {
+    pub fn is_word(&self, word: &str) -> bool {
+        matches!(self, TokenKind::Word(token) if token == word)
+    }
+}
+// the token as it could be written in the source, eg. for showing the words a macro expands to
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKind::Word(word) => write!(f, "{}", word),
+            TokenKind::Int(int) => write!(f, "{}", int),
+            TokenKind::Str(contents, quote) => write!(f, "{}{}{}", quote, contents, quote),
+            TokenKind::LenStr(contents, quote) => write!(f, "#{}{}{}", quote, contents, quote),
+            TokenKind::Comment(text) => write!(f, "/*{}*/", text),
+            TokenKind::Effect(effect) => write!(f, "/*:{}*/", effect),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+// parses a decimal, `0x` hex, `0b` binary or `0o` octal integer, which may have `_`s between its digits, eg. `1_000` or `0b1010_0101`
+// hex, binary and octal integers can also be the bits of a negative number, eg. `0xFFFFFFFFFFFFFFFF` is -1
+pub fn parse_int(word: &str) -> Option<Value> {
+    if let Ok(int) = word.parse::<Value>() {
+        return Some(int);
+    }
+    let (negative, unsigned) = match word.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, word),
+    };
+    let (radix, digits) = match unsigned.get(..2) {
+        Some("0x"|"0X") => (16, &unsigned[2..]),
+        Some("0b"|"0B") => (2, &unsigned[2..]),
+        Some("0o"|"0O") => (8, &unsigned[2..]),
+        _ => (10, unsigned),
+    };
+    if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        return None;
+    }
+    let digits = digits.replace('_', "");
+    if digits.is_empty() || !digits.chars().all(|chr| chr.is_digit(radix)) {
+        return None;
+    }
+    if radix == 10 {
+        return format!("{}{}", if negative { "-" } else { "" }, digits).parse::<Value>().ok();
+    }
+    let bits = u64::from_str_radix(&digits, radix).ok()?;
+    if negative && bits > Value::MIN.unsigned_abs() {
+        return None;
+    }
+    let int = bits as Value;
+    Some(if negative { int.wrapping_neg() } else { int })
+}
+
+fn is_whitespace(chr: char) -> bool {
+    "\t\x0c\r\n ".contains(chr)
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+impl Lexer {
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let chr = self.peek(0)?;
+        self.pos += 1;
+        if chr == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(chr)
+    }
+
+    fn at_comment(&self) -> bool {
+        self.peek(0) == Some('/') && matches!(self.peek(1), Some('/'|'*'))
+    }
+
+    // a `//` comment up to the end of the line, or a `/*` comment up to the next `*/` (or the end of the source),
+    // which is a stack effect annotation if it starts with `/*:`
+    fn comment(&mut self) -> TokenKind {
+        self.next();
+        let mut text = String::new();
+        if self.next() == Some('/') {
+            while let Some(chr) = self.peek(0).filter(|chr| *chr != '\n') {
+                text.push(chr);
+                self.next();
+            }
+        } else {
+            while let Some(chr) = self.next() {
+                if chr == '*' && self.peek(0) == Some('/') {
+                    self.next();
+                    break;
+                }
+                text.push(chr);
+            }
+            if let Some(effect) = text.strip_prefix(':') {
+                return TokenKind::Effect(effect.to_owned());
+            }
+        }
+        TokenKind::Comment(text)
+    }
+
+    // a string up to its closing quote, where a `\` escapes the character after it, or None if it is never closed
+    fn string(&mut self) -> Option<(String, char)> {
+        let quote = self.next()?;
+        let mut contents = String::new();
+        loop {
+            match self.next()? {
+                '\\' => {
+                    contents.push('\\');
+                    contents.push(self.next()?);
+                }
+                chr if chr == quote => return Some((contents, quote)),
+                chr => contents.push(chr),
+            }
+        }
+    }
+
+    // a character literal such as `c'-'` or `c'\n'` at the current position, which is the code point of the single character in it,
+    // along with how many characters it takes up, or None if there is not exactly one (possibly escaped) character in it, eg. `c'ab'`
+    fn char_literal(&self) -> Option<(Result<Value, String>, usize)> {
+        if self.peek(0) != Some('c') || self.peek(1) != Some('\'') {
+            return None;
+        }
+        let mut contents = String::new();
+        let mut len = 2;
+        loop {
+            match self.peek(len)? {
+                '\\' => {
+                    contents.push('\\');
+                    contents.push(self.peek(len + 1)?);
+                    len += 2;
+                }
+                '\'' => break,
+                chr => {
+                    contents.push(chr);
+                    len += 1;
+                }
+            }
+        }
+        match unescape(&contents).as_deref() {
+            Ok([chr]) => Some((Ok(*chr as Value), len + 1)),
+            Err(err) => Some((Err(err.clone()), len + 1)),
+            Ok(_) => None,
+        }
+    }
+
+    // anything up to whitespace or a comment, except that the value of a constant is a token of its own, eg. `:x;3` is `:x;` and `3`
+    fn word(&mut self) -> TokenKind {
+        let mut word = String::new();
+        while let Some(chr) = self.peek(0) {
+            if is_whitespace(chr) || self.at_comment() {
+                break;
+            }
+            word.push(chr);
+            self.next();
+            if chr == ';' && word.starts_with(':') {
+                break;
+            }
+        }
+        match parse_int(&word) {
+            Some(int) => TokenKind::Int(int),
+            None => TokenKind::Word(word),
+        }
+    }
+}
+
+fn hex_char(digits: &str) -> Option<char> {
+    if digits.is_empty() || !digits.chars().all(|chr| chr.is_ascii_hexdigit()) {
+        return None;
+    }
+    char::from_u32(u32::from_str_radix(digits, 16).ok()?)
+}
+
+// decodes the escapes in the contents of a string into the characters it contains, which are:
+// `\n`, `\t`, `\r`, `\0`, `\\`, `\'`, `\"`, `\xNN` (2 hex digits) and `\u{N}` (1 to 6 hex digits of a unicode code point)
+pub fn unescape(contents: &str) -> Result<Vec<char>, String> {
+    let mut chars = contents.chars();
+    let mut decoded: Vec<char> = Vec::new();
+    while let Some(chr) = chars.next() {
+        if chr != '\\' {
+            decoded.push(chr);
+            continue;
+        }
+        decoded.push(match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some(chr @ ('\\'|'\''|'"')) => chr,
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                match hex_char(&digits) {
+                    Some(chr) if digits.len() == 2 => chr,
+                    _ => return Err(format!("invalid escape \\x{} (expected 2 hex digits)", digits)),
+                }
+            }
+            Some('u') => {
+                let rest = chars.as_str();
+                let digits = rest.strip_prefix('{').and_then(|rest| rest.split_once('}')).map(|(digits, _)| digits);
+                match digits.filter(|digits| digits.len() <= 6).and_then(hex_char) {
+                    Some(chr) => {
+                        // skips the `{`, the digits and the `}`
+                        chars.nth(digits.unwrap_or_default().len() + 1);
+                        chr
+                    }
+                    None => return Err("invalid escape \\u (expected a unicode code point such as \\u{e9})".to_owned()),
+                }
+            }
+            Some(chr) => return Err(format!("unknown escape \\{}", chr)),
+            None => return Err("expected a character after \\".to_owned()),
+        });
+    }
+    Ok(decoded)
+}
+
+// splits source code into tokens, where strings start with a `'`, `"`, `#'`, `#"` or, for character literals of one character, `c'`
+// at the start of a token and comments can start anywhere outside of a string
+pub fn lex(chars: impl IntoIterator<Item = char>) -> Vec<Token> {
+    let mut lexer = Lexer { chars: chars.into_iter().collect(), pos: 0, line: 1, column: 1 };
+    let mut tokens: Vec<Token> = Vec::new();
+    while let Some(chr) = lexer.peek(0) {
+        if is_whitespace(chr) {
+            lexer.next();
+            continue;
+        }
+        let (start, line, column) = (lexer.pos, lexer.line, lexer.column);
+        let kind = if lexer.at_comment() {
+            Some(lexer.comment())
+        } else if chr == '\'' || chr == '"' {
+            lexer.string().map(|(contents, quote)| TokenKind::Str(contents, quote))
+        } else if chr == '#' && matches!(lexer.peek(1), Some('\''|'"')) {
+            lexer.next();
+            lexer.string().map(|(contents, quote)| TokenKind::LenStr(contents, quote))
+        } else if let Some((int, len)) = lexer.char_literal() {
+            (0..len).for_each(|_| _ = lexer.next());
+            match int {
+                Ok(int) => Some(TokenKind::Int(int)),
+                Err(err) => {
+                    super::print_err(format!("invalid character literal at line {} column {}: {}", line, column, err));
+                    continue;
+                }
+            }
+        } else {
+            Some(lexer.word())
+        };
+        match kind {
+            Some(kind) => tokens.push(Token { kind, span: Span { start, end: lexer.pos, line, column } }),
+            None => super::print_err(format!("unterminated string starting at line {} column {}", line, column)),
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        lex(source.chars()).into_iter().map(|token| token.kind).collect()
+    }
+
+    fn word(word: &str) -> TokenKind {
+        TokenKind::Word(word.to_owned())
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(kinds("\"ab\" 'c'"), [TokenKind::Str("ab".to_owned(), '"'), TokenKind::Str("c".to_owned(), '\'')]);
+        assert_eq!(kinds("#\"ab\" #'c'"), [TokenKind::LenStr("ab".to_owned(), '"'), TokenKind::LenStr("c".to_owned(), '\'')]);
+        assert_eq!(kinds("\"a b  c\" d"), [TokenKind::Str("a b  c".to_owned(), '"'), word("d")]);
+        assert_eq!(kinds("\"it's\""), [TokenKind::Str("it's".to_owned(), '"')]);
+        // the escaped `\` does not escape the quote after it
+        assert_eq!(kinds(r#""a\\" b"#), [TokenKind::Str(r"a\\".to_owned(), '"'), word("b")]);
+        assert_eq!(kinds(r#""a\"b""#), [TokenKind::Str(r#"a\"b"#.to_owned(), '"')]);
+        // a quote inside a word does not start a string
+        assert_eq!(kinds("a\"b"), [word("a\"b")]);
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(kinds("a // b c\nd"), [word("a"), TokenKind::Comment(" b c".to_owned()), word("d")]);
+        assert_eq!(kinds("a /* b\nc */ d"), [word("a"), TokenKind::Comment(" b\nc ".to_owned()), word("d")]);
+        assert_eq!(kinds("[f /*: a -- b */"), [word("[f"), TokenKind::Effect(" a -- b ".to_owned())]);
+        assert_eq!(kinds("\"// a\""), [TokenKind::Str("// a".to_owned(), '"')]);
+    }
+
+    #[test]
+    fn comment_after_word() {
+        assert_eq!(kinds("a//b"), [word("a"), TokenKind::Comment("b".to_owned())]);
+        assert_eq!(kinds("a/*b*/c"), [word("a"), TokenKind::Comment("b".to_owned()), word("c")]);
+        assert_eq!(kinds(":x/*: int */"), [word(":x"), TokenKind::Effect(" int ".to_owned())]);
+        // a `/` that does not start a comment is part of the word
+        assert_eq!(kinds("a/b /"), [word("a/b"), word("/")]);
+    }
+
+    #[test]
+    fn unterminated() {
+        assert_eq!(kinds("a \"b c"), [word("a")]);
+        assert_eq!(kinds("a #'b"), [word("a")]);
+        assert_eq!(kinds(r#""b\""#), []);
+        // a comment that is not closed goes to the end of the source
+        assert_eq!(kinds("a /* b c"), [word("a"), TokenKind::Comment(" b c".to_owned())]);
+    }
+
+    #[test]
+    fn spans() {
+        let tokens = lex("a\n  \"b c\" d".chars());
+        assert_eq!(tokens[1].span, Span { start: 4, end: 9, line: 2, column: 3 });
+        assert_eq!(tokens[2].span, Span { start: 10, end: 11, line: 2, column: 9 });
+    }
+
+    #[test]
+    fn constant_value() {
+        assert_eq!(kinds(":x;3 4"), [word(":x;"), TokenKind::Int(3), TokenKind::Int(4)]);
+        assert_eq!(kinds(":s;\"a b\""), [word(":s;"), TokenKind::Str("a b".to_owned(), '"')]);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(unescape(r"a\\b"), Ok(vec!['a', '\\', 'b']));
+        assert_eq!(unescape(r"\n\t\r\0"), Ok(vec!['\n', '\t', '\r', '\0']));
+        assert_eq!(unescape(r#"\'\""#), Ok(vec!['\'', '"']));
+        assert_eq!(unescape(r"\x41\x7e"), Ok(vec!['A', '~']));
+        assert_eq!(unescape(r"\u{e9}\u{1F600}!"), Ok(vec!['é', '😀', '!']));
+    }
+
+    #[test]
+    fn invalid_escapes() {
+        assert_eq!(unescape(r"\q"), Err("unknown escape \\q".to_owned()));
+        assert_eq!(unescape("a\\"), Err("expected a character after \\".to_owned()));
+        assert!(unescape(r"\x4").is_err());
+        assert!(unescape(r"\xzz").is_err());
+        assert!(unescape(r"\u{}").is_err());
+        assert!(unescape(r"\u{1234567}").is_err());
+        assert!(unescape(r"\u{110000}").is_err());
+        assert!(unescape(r"\u41").is_err());
+    }
+
+    #[test]
+    fn length_counts_characters() {
+        // the length of `#'é😀\\u{e9}'` is the number of characters rather than bytes
+        assert_eq!(kinds("#'é😀\\u{e9}'"), [TokenKind::LenStr("é😀\\u{e9}".to_owned(), '\'')]);
+        assert_eq!(unescape("é😀\\u{e9}").map(|chars| chars.len()), Ok(3));
+    }
+    #[test]
+    fn ints() {
+        assert_eq!(parse_int("42"), Some(42));
+        assert_eq!(parse_int("-42"), Some(-42));
+        assert_eq!(parse_int("0x1F"), Some(31));
+        assert_eq!(parse_int("0Xff"), Some(255));
+        assert_eq!(parse_int("0b1010"), Some(10));
+        assert_eq!(parse_int("0o17"), Some(15));
+        assert_eq!(parse_int("-0x10"), Some(-16));
+        assert_eq!(parse_int("1_000"), Some(1000));
+        assert_eq!(parse_int("0b1010_0101"), Some(0b1010_0101));
+        for invalid in ["0x", "0b2", "0o8", "0xg", "_1", "1_", "1__0", "0x_1", "1.5", "-", "x10"] {
+            assert_eq!(parse_int(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn negative_bit_patterns() {
+        assert_eq!(parse_int("0xFFFFFFFFFFFFFFFF"), Some(-1));
+        assert_eq!(parse_int("0x8000000000000000"), Some(Value::MIN));
+        assert_eq!(parse_int("-0x8000000000000000"), Some(Value::MIN));
+        assert_eq!(parse_int(&format!("0b1{}", "0".repeat(63))), Some(Value::MIN));
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(parse_int("9223372036854775807"), Some(Value::MAX));
+        assert_eq!(parse_int("9223372036854775808"), None);
+        assert_eq!(parse_int("-9223372036854775809"), None);
+        assert_eq!(parse_int("0x10000000000000000"), None);
+        assert_eq!(parse_int("-0x8000000000000001"), None);
+        // too big for a number, so it is a word
+        assert_eq!(kinds("99999999999999999999"), [word("99999999999999999999")]);
+    }
+
+    #[test]
+    fn char_literals() {
+        assert_eq!(kinds("c'a' c'-'"), [TokenKind::Int(97), TokenKind::Int(45)]);
+        assert_eq!(kinds(r"c'\n' c'\'' c'\\' c'\u{e9}' c'😀'"), [TokenKind::Int(10), TokenKind::Int(39), TokenKind::Int(92), TokenKind::Int(233), TokenKind::Int(0x1F600)]);
+        assert_eq!(kinds("c' '"), [TokenKind::Int(32)]);
+        // anything but one character is a word
+        assert_eq!(kinds("c'ab' c'' c'a"), [word("c'ab'"), word("c''"), word("c'a")]);
+        assert_eq!(kinds("can't"), [word("can't")]);
+        // an invalid escape is reported and left out
+        assert_eq!(kinds(r"c'\q' a"), [word("a")]);
+    }
+}
\ No newline at end of file
@@ -1,9 +1,13 @@
 mod stack;
+mod lex;
 mod parse;
+mod effect;
 mod debug;
 mod io;
 mod interpret;
 mod import;
+mod cli;
+mod project;
 
 //use stack::Stack;
 use parse::*;
@@ -15,40 +19,145 @@ fn print_err(err: impl std::fmt::Display) {
 }
 
 fn main() {
-    let mut interpreter: Interpreter = Interpreter::new();
-    let mut paths = Vec::new();
-    paths.push(std::path::Path::new("ss_src"));
-    let mut import_manager: import::ImportManager = import::ImportManager::new(&paths);
+    let args = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            print_err(err);
+            println!("{}", cli::USAGE);
+            std::process::exit(1);
+        }
+    };
+    let project = match args.project {
+        Some(path) => Some(project::Project::load(&path)),
+        None => project::Project::find(),
+    }.transpose().unwrap_or_else(|err| {
+        print_err(err);
+        std::process::exit(1);
+    });
+    let mut interpreter: Interpreter;
+    let mut paths = args.paths;
+    if let Some(project) = &project {
+        interpreter = Interpreter::with_memory(project.memory_size, project.heap_start);
+        paths.extend(project.search_paths());
+    } else {
+        interpreter = Interpreter::new();
+    }
+    let mut import_manager: import::ImportManager = import::ImportManager::new(import::search_paths(paths));
 
     let /* mut */ compiler_optimise: bool = true;
 
-    import::import_dir(&mut import_manager, &mut interpreter, std::path::Path::new("ss_src/stdlib"), compiler_optimise).unwrap();
+    if args.stdlib {
+        _ = import::import_dir(&mut import_manager, &mut interpreter, &std::path::Path::new(import::EMBEDDED_ROOT).join("stdlib"), compiler_optimise);
+    }
+
+    if let Some(project) = &project {
+        if let Some(entry) = &project.entry {
+            let entry = project.root.join(entry);
+            if let Err(()) = import::import_multiple(&mut import_manager, &mut interpreter, [entry.to_str().unwrap_or_default()], compiler_optimise) {
+                print_err(format!("failed to load project {}", project.name));
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(code) = &args.expand {
+        debug::print_expansion(&mut interpreter, code, compiler_optimise);
+        std::process::exit(0);
+    }
+
+    if let Some(project) = &project {
+        if interpreter.is_defined(&project.main) {
+            parse_program_code(project.main.chars(), interpreter.access_for_parsing(), compiler_optimise);
+            let (mut newline, mut err) = (true, false);
+            while !interpreter.input_required() && !err {
+                let out = interpreter.interpret();
+                (newline, err) = (out.printed, out.err);
+            }
+            if !newline {
+                println!();
+            }
+            if !args.interactive {
+                std::process::exit(if err { 1 } else { 0 });
+            }
+            println!("{}", interpreter.data_stack);
+        } else {
+            print_err(format!("project {} has no main function: {} is not defined", project.name, project.main));
+            if !args.interactive {
+                std::process::exit(1);
+            }
+        }
+    }
     
     let mut settings = std::collections::HashMap::from([
         ("show_heap", false),
         ("pause", false),
+        ("auto_reload", false),
+        ("auto_compact", false),
         #[cfg(debug_assertions)]
         ("show_pc", false),
     ]);
 
     loop {
         while interpreter.input_required() || settings["pause"] {
+            if settings["auto_reload"] {
+                import::reload_changed(&mut import_manager, &mut interpreter, compiler_optimise);
+            }
             let input = io::read_line(">> ");
-            if input.starts_with("///") {
-                let mut command_args = input[3..].split_ascii_whitespace();
+            if let Some(command) = input.strip_prefix("///") {
+                let mut command_args = command.split_ascii_whitespace();
                 match command_args.next() {
                     Some("import"|"dep:") => {
-                        if let Err(_) = import::import_multiple(&mut import_manager, &mut interpreter, command_args, compiler_optimise) {
+                        if let Err(()) = import::import_multiple(&mut import_manager, &mut interpreter, command_args, compiler_optimise) {
                             println!("failed to import files");
                         }
                     }
+                    Some("reload") => {
+                        let mut command_args = command_args.peekable();
+                        if command_args.next_if_eq(&"auto").is_some() {
+                            *settings.get_mut("auto_reload").unwrap() = !settings["auto_reload"];
+                            println!("auto reload: {}", if settings["auto_reload"] { "on" } else { "off" });
+                        } else if let Err(()) = import::reload(&mut import_manager, &mut interpreter, command_args, compiler_optimise) {
+                            println!("failed to reload files");
+                        }
+                    }
+                    Some("compact") => {
+                        if command_args.next() == Some("auto") {
+                            *settings.get_mut("auto_compact").unwrap() = !settings["auto_compact"];
+                            println!("auto compact: {}", if settings["auto_compact"] { "on" } else { "off" });
+                        } else {
+                            let total = interpreter.program_len();
+                            match interpreter.compact() {
+                                Ok(removed) => println!("removed {} of {} codes", removed, total),
+                                Err(err) => print_err(format!("cannot compact: {}", err)),
+                            }
+                        }
+                    }
+                    Some("deps"|"dependencies") => import_manager.print_dependencies(),
+                    Some("path"|"paths") => import_manager.print_paths(),
                     Some("clr"|"clear") => {
                         io::clear_screen();
                         break;
                     }
                     Some("heap"|"show_heap"|"hide_heap") => {
-                        *settings.get_mut("show_heap").unwrap() = !settings["show_heap"];
-                        break;
+                        let mut command_args = command_args.peekable();
+                        if command_args.peek().is_some() {
+                            debug::inspect_heap(&interpreter, command_args);
+                        } else {
+                            *settings.get_mut("show_heap").unwrap() = !settings["show_heap"];
+                            break;
+                        }
+                    }
+                    Some("vars"|"variables") => debug::print_vars(&interpreter),
+                    Some("expand") => debug::print_expansion(&mut interpreter, command.trim_start()["expand".len()..].trim(), compiler_optimise),
+                    Some("watch"|"unwatch") => debug::watch(&mut interpreter, command_args),
+                    Some("overflow") => {
+                        match command_args.next() {
+                            Some(name) => match interpret::OverflowMode::from_name(name) {
+                                Some(mode) => interpreter.overflow_mode = mode,
+                                None => print_err(format!("unknown overflow mode {} (expected wrapping, checked, saturating or big)", name)),
+                            }
+                            None => println!("overflow: {}", interpreter.overflow_mode.name()),
+                        }
                     }
                     Some("pause"|"unpause"|"p") => {
                         *settings.get_mut("pause").unwrap() = !settings["pause"];
@@ -64,13 +173,21 @@ fn main() {
             //println!("{:?}", input.as_bytes());
             parse_program_code(input.chars(), interpreter.access_for_parsing(), compiler_optimise);
         }
-        let interpret::InterpreterOut { printed: newline, err } = interpreter.interpret();
+        let interpret::InterpreterOut { printed: newline, err, watched } = interpreter.interpret();
         if !newline {
-            println!("");
+            println!();
+        }
+        if let Some((addr, previous)) = watched {
+            debug::print_watch_hit(&interpreter, addr, previous);
+            *settings.get_mut("pause").unwrap() = true;
+        }
+        if settings["auto_compact"] && !err {
+            // fails quietly while a definition is unfinished, it is retried after the next line
+            _ = interpreter.compact();
         }
         println!("{}", interpreter.data_stack);
         if settings["show_heap"] {
-            debug::print_heap(&interpreter.memory, &interpreter.heap_pointer, &interpreter.heap_free_pointer);
+            debug::print_heap(&interpreter.memory, interpreter.heap_start, &interpreter.heap_pointer, &interpreter.heap_free_pointer);
         }
         #[cfg(debug_assertions)]
         if settings["show_pc"] {
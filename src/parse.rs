@@ -1,172 +1,439 @@
-use super::interpret::{ProgramCode, OpCode, Variable};
-
-//: format program code
-pub fn format_and_split_program_code(chars: impl IntoIterator<Item = char>) -> Vec<String> {
-    let mut split_str: Vec<String> = Vec::new();
-    let mut current_str = String::new();
-    let mut last_chr: char = ' ';
-    let mut in_double_quote: bool = false;
-    let mut in_single_quote: bool = false;
-    let mut line_comment: bool = false;
-    let mut block_comment: bool = false;
-    macro_rules! push_current_string { () => {
-        if !current_str.is_empty() {
-            split_str.push(current_str);
-            current_str = String::new();
+use super::{lex::{Span, TokenKind}, effect::{Annotations, StackEffect, Type}};
+use super::interpret::{Block, BlockKind, ExpandedWord, ForwardCalls, MacroCode, ProgramCode, OpCode, FloatOpCode, OverflowMode, Value, Variable, Cell, apply_op, apply_big_op, apply_float_op, from_float, to_float};
+
+pub struct ParserIn<'a> {
+    pub program_codes: &'a mut Vec<ProgramCode>,
+    pub macro_codes: &'a mut Vec<MacroCode>,
+    pub macro_expansions: &'a mut usize,
+    pub variables: &'a mut std::collections::HashMap<String, Variable>,
+    // the memory before the heap, where variables are stored
+    pub memory: &'a mut [Cell],
+    pub var_pointer: &'a mut usize,
+    pub pc: usize,
+    pub overflow_mode: OverflowMode,
+    // when recording, each name defined along with what it was before its first definition
+    pub definitions: &'a mut Option<Vec<(String, Option<Variable>)>>,
+    // when expanding, each word parsed and the codes it compiled to
+    pub expansion: &'a mut Option<Vec<ExpandedWord>>,
+    pub annotations: &'a mut Annotations,
+    pub late_calls: &'a mut Vec<String>,
+    pub forward_calls: &'a mut ForwardCalls,
+    pub blocks: &'a mut Vec<Block>,
+}
+
+// a float literal must have a '.' and start with a digit so that words like `inf` and `.` are left alone
+fn parse_float(word: &str) -> Option<f64> {
+    if word.contains('.') && word.trim_start_matches('-').starts_with(|c: char| c.is_ascii_digit()) {
+        word.parse::<f64>().ok()
+    } else {
+        None
+    }
+}
+
+// the error for a closer that does not close the innermost open block
+fn block_err(closer: &str, span: Span, open: Option<&Block>) -> String {
+    match open {
+        Some(block) if block.kind == BlockKind::Else => format!("`{}` at line {} column {} cannot follow `)!` at line {} column {}, which must be followed by a `(`",
+            closer, span.line, span.column, block.span.line, block.span.column),
+        Some(block) => format!("`{}` at line {} column {} cannot close `{}` at line {} column {}",
+            closer, span.line, span.column, block.kind.opener(), block.span.line, block.span.column),
+        None => format!("`{}` at line {} column {} has nothing to close", closer, span.line, span.column),
+    }
+}
+
+// ends a block as if its closer had been written, except for a loop, which is left without an end
+pub fn close_block(block: Block, program_codes: &mut Vec<ProgramCode>) {
+    use std::num::NonZeroUsize;
+    let len = program_codes.len();
+    match block.kind {
+        BlockKind::Func => {
+            program_codes[block.loc] = ProgramCode::FuncStart(NonZeroUsize::new(len));
+            program_codes.push(ProgramCode::FuncEnd);
         }
-    } }
-    for chr in chars.into_iter().chain(std::iter::once('\n')) {
-        if in_double_quote {
-            current_str.push(chr);
-            if chr == '"' {
-                if !current_str.ends_with("\\\"") {
-                    push_current_string!();
-                    in_double_quote = false;
-                }
-            }
-        } else if in_single_quote {
-            current_str.push(chr);
-            if chr == '\'' {
-                if !current_str.ends_with("\\'") {
-                    push_current_string!();
-                    in_single_quote = false;
+        BlockKind::If => {
+            program_codes[block.loc] = ProgramCode::If(NonZeroUsize::new(len));
+            program_codes.push(ProgramCode::EndIf);
+        }
+        // as if it were followed by `0 ( )`
+        BlockKind::Else => {
+            program_codes[block.loc] = ProgramCode::ElseIf(NonZeroUsize::new(len + 1));
+            program_codes.extend([ProgramCode::Int(0), ProgramCode::If(NonZeroUsize::new(len + 2)), ProgramCode::EndIf]);
+        }
+        BlockKind::Loop => {}
+    }
+}
+
+//: macro expansion
+// how many macros deep a word can be expanded from, so that a macro that expands to itself stops
+const MAX_MACRO_DEPTH: usize = 100;
+
+// a word with where it is in the source and how many macros deep it was expanded from
+type Word = (TokenKind, Span, usize);
+
+fn is_param(param: &str) -> bool {
+    param.starts_with(|chr: char| chr.is_alphabetic() || chr == '_') && param.chars().all(|chr| chr.is_alphanumeric() || chr == '_')
+}
+
+// the names and parameters of a macro from its definition without the `[;`, eg. `name|alias` or `name(a,b)`
+fn macro_signature(signature: &str) -> (Vec<String>, Vec<String>) {
+    let (names, params) = match signature.strip_suffix(')').and_then(|signature| signature.split_once('(')) {
+        Some((names, params)) if !names.is_empty() && params.split(',').all(is_param) => (names, params.split(',').map(str::to_owned).collect()),
+        _ => (signature, Vec::new()),
+    };
+    (names.split('|').map(str::to_owned).collect(), params)
+}
+
+// splits a word into the prefix it declares or refers to names with, the names (separated by `|`s) and anything after them,
+// eg. `[;`, `foo|bar`, `(a,b)` or `**`, `foo`, ``
+fn split_names(word: &str) -> (&str, &str, &str) {
+    let prefix_len = if word.starts_with("[;") {
+        2
+    } else if word.starts_with(['[', ':', ';', '@']) {
+        1
+    } else {
+        word.chars().take_while(|chr| *chr == '*').count()
+    };
+    let (prefix, rest) = word.split_at(prefix_len);
+    let end = match prefix {
+        "[" | "[;" | ":" => rest.find([';', '(', ']']).unwrap_or(rest.len()),
+        _ => rest.len(),
+    };
+    (prefix, &rest[..end], &rest[end..])
+}
+
+// a word as it was written, without the numbers that names declared in a macro are renamed with, eg. `:i` for `:i 12`
+pub fn unmangle(word: &str) -> String {
+    let mut parts = word.split(' ');
+    let first = parts.next().unwrap_or_default().to_owned();
+    parts.fold(first, |word, part| word + part.trim_start_matches(|chr: char| chr.is_ascii_digit()))
+}
+
+// the names of the variables, functions and macros declared in a macro body
+fn declared_names(body: &[TokenKind]) -> Vec<&str> {
+    body.iter()
+        .filter_map(|token| if let TokenKind::Word(word) = token { Some(word) } else { None })
+        .filter(|word| word.len() > 1 && word.starts_with(['[', ':']))
+        .flat_map(|word| split_names(word).1.split('|'))
+        .filter(|name| !name.is_empty() && !name.starts_with('$'))
+        .collect()
+}
+
+// the words in a `$(` `$)` group, after its `$(`, or None if it is not closed
+fn take_group(words: &mut std::vec::IntoIter<Word>) -> Option<Vec<TokenKind>> {
+    let mut group: Vec<TokenKind> = Vec::new();
+    let mut depth: usize = 0;
+    loop {
+        match words.next() {
+            Some((token, ..)) if token.is_word("$)") && depth == 0 => return Some(group),
+            Some((token, ..)) => {
+                if token.is_word("$(") {
+                    depth += 1;
+                } else if token.is_word("$)") {
+                    depth -= 1;
                 }
+                group.push(token);
             }
-        } else if line_comment {
-            if chr == '\n' {
-                current_str.clear();
-                line_comment = false;
-            }
-        } else if block_comment {
-            if last_chr == '*' && chr == '/' {
-                current_str.clear();
-                block_comment = false;
-                last_chr = ' ';
-            } else {
-                last_chr = chr;
-            }
-        } else if "\t\x0c\r\n ".contains(chr) {
-            push_current_string!();
-            if line_comment && chr == '\n' {
-                current_str.clear();
-                line_comment = false;
+            None => return None,
+        }
+    }
+}
+
+// the words a macro expands to, where each parameter takes an argument from the words after it, which is either a word or the
+// words in a `$(` `$)` group, and the names declared in the body are renamed, so that they cannot clash with names where it is used
+fn expand_macro(name: &str, body: &[TokenKind], params: &[String], words: &mut std::vec::IntoIter<Word>, expansion: usize) -> Result<Vec<TokenKind>, String> {
+    let mut args: Vec<Vec<TokenKind>> = Vec::new();
+    for _ in params {
+        args.push(match words.next() {
+            Some((token, ..)) if token.is_word("$(") => take_group(words).ok_or_else(|| format!("unclosed $( in an argument to {}", name))?,
+            Some((token, ..)) => vec![token],
+            None => return Result::Err(format!("{} expects {} arguments but got {}", name, params.len(), args.len())),
+        });
+    }
+    let declared = declared_names(body);
+    let mut expanded: Vec<TokenKind> = Vec::new();
+    for token in body {
+        let TokenKind::Word(word) = token else {
+            expanded.push(token.clone());
+            continue;
+        };
+        let (prefix, names, rest) = split_names(word);
+        if let Some(i) = names.strip_prefix('$').and_then(|param| params.iter().position(|name| name == param)) {
+            match args[i].as_slice() {
+                arg if prefix.is_empty() && rest.is_empty() => expanded.extend(arg.iter().cloned()),
+                [arg] => expanded.push(TokenKind::Word(format!("{}{}{}", prefix, arg, rest))),
+                _ => return Result::Err(format!("the argument for ${} in {} must be a single word", params[i], name)),
             }
+        } else if names.split('|').any(|name| declared.contains(&name)) {
+            // a space can never be written in a name, so the new names are only used by this expansion
+            let renamed: Vec<String> = names.split('|')
+                .map(|name| if declared.contains(&name) { format!("{} {}", name, expansion) } else { name.to_owned() })
+                .collect();
+            expanded.push(TokenKind::Word(format!("{}{}{}", prefix, renamed.join("|"), rest)));
         } else {
-            current_str.push(chr);
-            if chr == '"' {
-                if current_str.len() == 1 {
-                    in_double_quote = true;
-                }
-            } if chr == '\'' {
-                if current_str.len() == 1 {
-                    in_single_quote = true;
-                }
-            } else if current_str.ends_with("//") {
-                current_str.truncate(current_str.len().saturating_sub(2));
-                push_current_string!();
-                line_comment = true;
-            } else if current_str.ends_with("/*") {
-                current_str.truncate(current_str.len().saturating_sub(2));
-                push_current_string!();
-                block_comment = true;
-            }
+            expanded.push(token.clone());
         }
     }
-    return split_str;
+    Ok(expanded)
 }
 //;
 
-pub struct ParserIn<'a> {
-    pub program_codes: &'a mut Vec<ProgramCode>,
-    pub macro_codes: &'a mut Vec<(Vec<String>, Vec<String>, u8)>,
-    pub variables: &'a mut std::collections::HashMap<String, Variable>,
-    pub memory: &'a mut [i64; super::interpret::MEMORY_SIZE],
-    pub var_pointer: &'a mut usize,
-    pub pc: usize,
+//: parse program code
+// whether defining a constant replaces something defined in the same module, rather than declaring it again with the same value
+fn redefines_constant(previous: Option<&Variable>, values: &[Value], declared_here: bool) -> bool {
+    match previous {
+        Some(Variable::Constant(previous)) => declared_here && previous != values,
+        Some(_) => declared_here,
+        None => false,
+    }
 }
 
-//: parse program code
 pub fn parse_program_code(chars: impl IntoIterator<Item = char>, interpreter: ParserIn, compiler_optimise: bool) {
-    let ParserIn { program_codes, macro_codes,
-        variables, memory, var_pointer, pc } = interpreter;
+    let words = super::lex::lex(chars).into_iter()
+        .filter(|token| !matches!(token.kind, TokenKind::Comment(_)))
+        .map(|token| (token.kind, token.span, 0))
+        .collect();
+    parse_words(words, interpreter, compiler_optimise);
+}
+
+// compiles the words from lexing source code, or the words of a constant's value, returning whether every word compiled
+fn parse_words(words: Vec<Word>, interpreter: ParserIn, compiler_optimise: bool) -> bool {
+    let ParserIn { program_codes, macro_codes, macro_expansions,
+        variables, memory, var_pointer, pc, overflow_mode, definitions, late_calls, forward_calls, blocks, expansion, annotations } = interpreter;
     //println!("{:?}", format_and_split_program_code(string));
     use OpCode::*;
     use ProgramCode::*;
     use super::interpret::Variable::*;
-    let mut words = format_and_split_program_code(chars.into_iter()).into_iter();
-    let mut word: String;
-    loop {
-        if let Some(next) = words.next() {
-            word = next.to_string();
-        } else {
-            break;
+    macro_rules! define {
+        ($name:expr, $value:expr) => { {
+            let name: String = $name;
+            let previous = variables.insert(name.clone(), $value);
+            if let Some(definitions) = definitions.as_mut() {
+                if !definitions.iter().any(|(defined, _)| *defined == name) {
+                    definitions.push((name, previous));
+                }
+            }
+        } };
+    }
+    let mut words = words.into_iter();
+    let mut compiled = true;
+    // the address of the variable declared by the last word, which a `/*: type */` after it gives a type to
+    let mut last_declared = None;
+    while let Some((token, span, depth)) = words.next() {
+        let declared = last_declared.take();
+        if let Some(expansion) = expansion.as_mut() {
+            if let Some(last) = expansion.last_mut() {
+                last.codes.end = program_codes.len();
+            }
+            expansion.push(ExpandedWord { word: token.to_string(), depth, codes: program_codes.len()..program_codes.len() });
         }
-        if let Some(last) = macro_codes.last_mut() {
-            if word.starts_with('[') && word.len() > 1 {
-                if word[1..].starts_with(';') && word.len() > 2 {
-                    macro_codes.push((word[2..].split("|").map(|x| x.to_owned()).collect(), Vec::new(), 0));
-                    continue;
+        // replaces a macro with the words it expands to, which are where the macro was used in the source
+        macro_rules! expand {
+            ($name:expr, $body:expr, $params:expr) => {
+                if depth >= MAX_MACRO_DEPTH {
+                    super::print_err(format!("{} at line {} column {} is expanded more than {} macros deep, does it expand to itself?",
+                        unmangle($name), span.line, span.column, MAX_MACRO_DEPTH));
+                    compiled = false;
+                    // the rest of the expansion is left out so that the error is only shown once
+                    words = words.filter(|(.., depth)| *depth == 0).collect::<Vec<Word>>().into_iter();
                 } else {
-                    last.2 += 1;
+                    *macro_expansions += 1;
+                    match expand_macro(&unmangle($name), $body, $params, &mut words, *macro_expansions) {
+                        Ok(expanded) => {
+                            let mut new_words: Vec<Word> = expanded.into_iter().map(|word| (word, span, depth + 1)).collect();
+                            new_words.extend(words);
+                            words = new_words.into_iter();
+                        }
+                        Result::Err(err) => {
+                            super::print_err(format!("{} at line {} column {}", err, span.line, span.column));
+                            compiled = false;
+                        }
+                    }
                 }
-            } else if word.starts_with(':') && word.len() > 1 && word.contains(';') {
-                let parts = word[1..].rsplit_once(';').unwrap_or_default();
-                if !variables.contains_key(parts.0) {
-                    variables.insert(parts.0.to_owned(), Macro(format_and_split_program_code(parts.1.chars().into_iter())));
+            };
+        }
+        // evaluates the value of a constant once, when it is defined, by compiling it on its own and folding it to numbers
+        macro_rules! define_constant {
+            ($name:expr) => { {
+                let name: &str = $name;
+                // the value is the words in a `$(` `$)` group, eg. `:x;$( 3 4 + $)`, or otherwise the rest of the line,
+                // up to a `]` ending the function or macro it is in, eg. `:x;3 4 +`
+                let value = if words.as_slice().first().is_some_and(|(next, next_span, _)| next_span.line == span.line && next.is_word("$(")) {
+                    words.next();
+                    take_group(&mut words)
+                } else {
+                    let mut value: Vec<TokenKind> = Vec::new();
+                    while let Some((next, ..)) = words.as_slice().first()
+                            .filter(|(next, next_span, next_depth)| next_span.line == span.line && *next_depth == depth && !next.is_word("]")) {
+                        value.push(next.clone());
+                        words.next();
+                    }
+                    Some(value)
+                };
+                let mut codes: Vec<ProgramCode> = Vec::new();
+                let value_compiled = parse_words(value.iter().flatten().map(|token| (token.clone(), span, depth)).collect(), ParserIn {
+                    program_codes: &mut codes, macro_codes: &mut Vec::new(), macro_expansions: &mut *macro_expansions,
+                    variables: &mut *variables, memory: &mut *memory, var_pointer: &mut *var_pointer, pc: 0, overflow_mode,
+                    definitions: &mut *definitions, expansion: &mut None, annotations: &mut Annotations::default(),
+                    late_calls: &mut *late_calls, forward_calls: &mut ForwardCalls::default(), blocks: &mut Vec::new(),
+                }, true);
+                match codes.iter().map(|code| if let Int(value) = code { Some(*value) } else { None }).collect::<Option<Vec<Value>>>() {
+                    _ if value.is_none() => {
+                        super::print_err(format!("unclosed $( in the value of {} at line {} column {}", unmangle(name), span.line, span.column));
+                        compiled = false;
+                    }
+                    // a word that did not compile, eg. an unknown one, is left out of the codes, so would change the value
+                    Some(values) if value_compiled && !values.is_empty() => {
+                        // a name from another module is shadowed, as with variables
+                        let declared_here = definitions.as_ref().is_none_or(|definitions| definitions.iter().any(|(defined, _)| defined == name));
+                        if redefines_constant(variables.get(name), &values, declared_here) {
+                            super::print_err(format!("{} is redefined at line {} column {}", unmangle(name), span.line, span.column));
+                        }
+                        define!(name.to_owned(), Constant(values));
+                    }
+                    _ => {
+                        super::print_err(format!("the value of {} at line {} column {} is not a constant", unmangle(name), span.line, span.column));
+                        compiled = false;
+                    }
                 }
-                continue;
-            } else if word == "]" {
-                if last.2 == 0 {
-                    for name in last.0.drain(..) {
-                        variables.insert(name, Macro(last.1.clone()));
+            } };
+        }
+        if let Some(last) = macro_codes.last_mut() {
+            if let TokenKind::Word(word) = &token {
+                if word.starts_with('[') && word.len() > 1 {
+                    if word[1..].starts_with(';') && word.len() > 2 {
+                        let (names, params) = macro_signature(&word[2..]);
+                        macro_codes.push((names, params, Vec::new(), 0));
+                        continue;
+                    } else if !word.ends_with(']') {
+                        last.3 += 1;
                     }
-                    macro_codes.pop();
+                } else if let Some(name) = word.strip_prefix(':').and_then(|word| word.strip_suffix(';')) {
+                    define_constant!(name);
                     continue;
-                } else {
-                    last.2 -= 1;
+                } else if word == "]" {
+                    if last.3 == 0 {
+                        for name in last.0.drain(..) {
+                            define!(name, Macro(last.2.clone(), last.1.clone()));
+                        }
+                        macro_codes.pop();
+                        continue;
+                    } else {
+                        last.3 -= 1;
+                    }
                 }
             }
-            last.1.push(word);
+            last.2.push(token);
         }
-        else if let Some(var) = variables.get(&word) {
-            match var {
-                Variable(value) => program_codes.push(Int(*value as i64)),
-                Function(value) => program_codes.push(FuncCall(*value as usize)),
-                Macro(value) => {
-                    let mut new_words = value.clone();
-                    new_words.extend(words);
-                    words = new_words.into_iter();
+        else if let TokenKind::Int(int) = token {
+            program_codes.push(Int(int));
+        } else if let TokenKind::Str(contents, quote) | TokenKind::LenStr(contents, quote) = &token {
+            match super::lex::unescape(contents) {
+                Ok(string) => {
+                    if *quote == '"' {
+                        program_codes.push(Int(-1));
+                    }
+                    // each character is pushed as its unicode code point
+                    program_codes.extend(string.iter().rev().map(|chr| Int(*chr as Value)));
+                    if let TokenKind::LenStr(..) = token { // length prefixed
+                        program_codes.push(Int(string.len() as Value));
+                    }
+                }
+                Result::Err(err) => {
+                    super::print_err(format!("{} in {}", err, token));
+                    compiled = false;
                 }
             }
-        } else {
-            if word.starts_with(':') && word.len() > 1 {
-                let name = word[1..].to_owned();
-                if let Some(parts) = name.split_once(';') {
-                    if !variables.contains_key(parts.0) {
-                        variables.insert(parts.0.to_owned(), Macro(format_and_split_program_code(parts.1.chars().into_iter())));
+        } else if let TokenKind::Effect(annotation) = &token {
+            if let Some(addr) = declared.filter(|_| !annotation.contains("--")) {
+                // gives a type to the variable it is straight after the declaration of
+                match Type::parse(annotation.trim()) {
+                    Some(kind) => _ = annotations.variables.insert(addr, kind),
+                    None => super::print_err(format!("unknown type {} at line {} column {} (expected {})",
+                        annotation.trim(), span.line, span.column, Type::NAMES)),
+                }
+            } else {
+                // annotates the function it is straight after the name of
+                match (blocks.last(), StackEffect::parse(annotation)) {
+                    (Some(block), Ok(effect)) if block.kind == BlockKind::Func && block.loc + 1 == program_codes.len() => {
+                        annotations.effects.insert(block.loc, effect);
                     }
+                    (_, Result::Err(err)) => super::print_err(format!("invalid stack effect at line {} column {}: {}", span.line, span.column, err)),
+                    _ => super::print_err(format!("the stack effect at line {} column {} must be straight after a function's name", span.line, span.column)),
+                }
+            }
+        } else if let TokenKind::Word(word) = token {
+            if let Some(var) = variables.get(&word) {
+                match var {
+                    Variable(value) => {
+                        if blocks.iter().any(|block| block.kind == BlockKind::Func) {
+                            annotations.addresses.insert(program_codes.len(), *value);
+                        }
+                        program_codes.push(Int(*value as Value));
+                    }
+                    Function(value) => program_codes.push(FuncCall(*value)),
+                    Macro(body, params) => expand!(&word, body, params),
+                    Constant(values) => program_codes.extend(values.iter().map(|value| Int(*value))),
+                }
+            } else if word.starts_with(':') && word.len() > 1 {
+                let name = word[1..].to_owned();
+                if let Some(name) = name.strip_suffix(';') {
+                    define_constant!(name);
                 } else {
-                    if !variables.contains_key(&name) {
-                        variables.insert(name.to_owned(), Variable(*var_pointer));
-                        memory[*var_pointer] = 0;
-                        *var_pointer += 1;
+                    // a module gets its own variable rather than sharing one of the same name declared outside it
+                    let declared_here = definitions.as_ref().is_none_or(|definitions| definitions.iter().any(|(defined, _)| *defined == name));
+                    match variables.get(&name) {
+                        // declared again, which is the same variable
+                        Some(Variable(addr)) if declared_here => last_declared = Some(*addr),
+                        Some(_) if declared_here => {}
+                        _ if *var_pointer >= memory.len() => {
+                            super::print_err(format!("cannot declare {}: out of memory for variables", unmangle(&name)));
+                            compiled = false;
+                        }
+                        _ => {
+                            define!(name.to_owned(), Variable(*var_pointer));
+                            memory[*var_pointer] = Cell::default();
+                            last_declared = Some(*var_pointer);
+                            *var_pointer += 1;
+                        }
                     }
                 }
+            } else if word.starts_with('@') && word[1..].starts_with(|chr: char| chr.is_alphabetic() || chr == '_') {
+                // late bound, so the name does not need to be defined yet
+                late_calls.push(word[1..].to_owned());
+                program_codes.push(LateCall(late_calls.len() - 1));
             } else if word.starts_with('*') && word.contains(|x| x != '*') {
                 let num = word.chars().take_while(|c| *c == '*').count();
                 let name = &word[num..];
                 if let Some(Variable(loc)) = variables.get(name) {
-                    program_codes.extend(std::iter::once(Int(*loc as i64)).chain([PopMemLoc, PushMem].into_iter().cycle().take(num * 2)));
+                    if blocks.iter().any(|block| block.kind == BlockKind::Func) {
+                        annotations.addresses.insert(program_codes.len(), *loc);
+                    }
+                    program_codes.extend(std::iter::once(Int(*loc as Value)).chain([PopMemLoc, PushMem].into_iter().cycle().take(num * 2)));
                 } else {
-                    println!("!? {} ?", word);
+                    println!("!? {} ?", unmangle(&word));
+                    compiled = false;
                 }
             } else if word.starts_with('[') && word.len() > 1 {
                 if word[1..].starts_with(';') && word.len() > 2 {
-                    macro_codes.push((word[2..].split("|").map(|x| x.to_owned()).collect(), Vec::new(), 0));
+                    let (names, params) = macro_signature(&word[2..]);
+                    macro_codes.push((names, params, Vec::new(), 0));
+                } else if let Some(names) = word[1..].strip_suffix(']').filter(|names| !names.is_empty()) {
+                    // a forward declaration, eg. `[foo]`
+                    forward_calls.declared.extend(names.split("|").map(str::to_owned));
                 } else {
+                    let loc = program_codes.len();
                     for name in word[1..].split("|") {
-                        variables.insert(name.to_owned(), Function(program_codes.len()));
+                        define!(name.to_owned(), Function(loc));
+                        // calls made before the function was defined can now call it directly
+                        forward_calls.calls.retain(|(called, call_loc)| {
+                            if called == name {
+                                program_codes[*call_loc] = FuncCall(loc);
+                            }
+                            called != name
+                        });
+                        forward_calls.declared.retain(|declared| declared != name);
                     }
+                    blocks.push(Block { kind: BlockKind::Func, loc, span });
                     program_codes.push(FuncStart(None));
                 }
             } else if word.starts_with(';') && word.len() > 1 {
@@ -190,96 +457,89 @@ pub fn parse_program_code(chars: impl IntoIterator<Item = char>, interpreter: Pa
                             }
                         }
                     }
-                    Some(Macro(value)) => {
-                        let mut new_words = value.clone();
-                        new_words.extend(words);
-                        words = new_words.into_iter();
-                    }
-                    _ => println!("!? {} ?", word)
-                }
-            } else if (word.starts_with('"') && word.len() > 1 && word.ends_with('"')) || (word.starts_with("#\"") && word.len() > 2 && word.ends_with('"')) || 
-                    (word.starts_with('\'') && word.len() > 1 && word.ends_with('\'')) || (word.starts_with("#'") && word.len() > 2 && word.ends_with('\'')) {
-                let start = if word.starts_with('#') { 2 } else { 1 };
-                let string = &word[start..word.len()-1];
-                if word.ends_with('"') {
-                    program_codes.push(Int(-1));
-                }
-                let mut previous: Option<char> = None;
-                for chr in string.chars().rev() {
-                    if chr == '\\' {
-                        if let Some(prev) = previous {
-                            program_codes.push(Int(match prev {
-                                'n' => '\n',
-                                't' => '\t',
-                                'r' => '\r',
-                                _ => prev
-                            } as i64));
-                            previous = None;
-                        } else {
-                            previous = Some('\\');
-                        }
-                    } else {
-                        if let Some(prev) = previous {
-                            program_codes.push(Int(prev as i64));
-                        }
-                        previous = Some(chr);
+                    Some(Macro(body, params)) => expand!(&word, body, params),
+                    Some(Constant(values)) => program_codes.extend(values.iter().map(|value| Int(*value))),
+                    _ => {
+                        println!("!? {} ?", unmangle(&word));
+                        compiled = false;
                     }
                 }
-                if let Some(prev) = previous {
-                    program_codes.push(Int(prev as i64));
-                }
-                if start == 2 { // length prefixed
-                    program_codes.push(Int(string.len() as i64));
-                }
             } else {
-                macro_rules! end_if {
-                    () => {   
-                        if let Some(loc) = program_codes.iter().rposition(|x| matches!(x, If(None))) {
-                            program_codes[loc] = If(std::num::NonZeroUsize::new(program_codes.len()));
+                // the location of the code that opened the innermost block, which is closed, if it is of the kind
+                macro_rules! close {
+                    ($kind:expr) => {
+                        match blocks.last() {
+                            Some(block) if block.kind == $kind => blocks.pop().map(|block| block.loc),
+                            open => {
+                                super::print_err(block_err(&word, span, open));
+                                // the blocks inside the one it can close are ended so that a mistake does not swallow the code after it,
+                                // and then the code stops, since the blocks may not be what was meant, eg. a loop without a way out
+                                let loc = if $kind == BlockKind::If && open.is_some_and(|block| block.kind == BlockKind::Else) {
+                                    blocks.pop().into_iter().for_each(|block| close_block(block, program_codes));
+                                    None
+                                } else if let Some(i) = blocks.iter().rposition(|block| block.kind == $kind) {
+                                    blocks.drain(i + 1..).rev().for_each(|block| close_block(block, program_codes));
+                                    blocks.pop().map(|block| block.loc)
+                                } else {
+                                    None
+                                };
+                                program_codes.push(Err);
+                                loc
+                            }
                         }
                     };
                 }
                 if let Some(code) = 
                     match word.as_str() {
-                        "]" => {
-                            if let Some(loc) = program_codes.iter().rposition(|x| matches!(x, FuncStart(None))) {
-                                program_codes[loc] = FuncStart(std::num::NonZeroUsize::new(program_codes.len()));
+                        "]" => close!(BlockKind::Func).map(|loc| {
+                            if let Some(err) = super::effect::check(program_codes, loc, program_codes.len(), annotations, variables) {
+                                let name = variables.iter()
+                                    .filter(|(_, var)| **var == Function(loc))
+                                    .map(|(name, _)| name.as_str())
+                                    .min()
+                                    .unwrap_or_default();
+                                super::print_err(format!("{} (ending at line {}) does not do what its stack effect says: {}", unmangle(name), span.line, err));
                             }
-                            Some(FuncEnd)
-                        }
+                            // only the function being checked needs to know which numbers are variables
+                            annotations.addresses.retain(|addr_loc, _| *addr_loc < loc);
+                            program_codes[loc] = FuncStart(std::num::NonZeroUsize::new(program_codes.len()));
+                            FuncEnd
+                        }),
                         "." => Some(PopMemLoc),
                         "<" => Some(PushMem),
                         ">" => Some(PopMem),
                         "!?" => Some(Err),
                         "(" => {
-                            let mut back_pc = program_codes.len();
-                            let mut depth = 0;
-                            while back_pc > 0 { back_pc -= 1;
-                                match program_codes[back_pc] {
-                                    EndIf | ElseIf(Some(_)) => depth += 1,
-                                    If(_) => depth -= 1,
-                                    ElseIf(None) => {
-                                        program_codes[back_pc] = ElseIf(std::num::NonZeroUsize::new(program_codes.len()));
-                                    }
-                                    _ => {}
-                                }
-                                if depth < 0 {
-                                    break;
-                                }
+                            if let Some(block) = blocks.last().copied().filter(|block| block.kind == BlockKind::Else) {
+                                program_codes[block.loc] = ElseIf(std::num::NonZeroUsize::new(program_codes.len()));
+                                blocks.pop();
                             }
+                            blocks.push(Block { kind: BlockKind::If, loc: program_codes.len(), span });
                             Some(If(None))
                         }
-                        ")!" => { end_if!(); Some(ElseIf(None)) },
-                        ")" => { end_if!(); Some(EndIf) },
-                        "{" => Some(Loop),
+                        ")!" => close!(BlockKind::If).map(|loc| {
+                            program_codes[loc] = If(std::num::NonZeroUsize::new(program_codes.len()));
+                            blocks.push(Block { kind: BlockKind::Else, loc: program_codes.len(), span });
+                            ElseIf(None)
+                        }),
+                        ")" => close!(BlockKind::If).map(|loc| {
+                            program_codes[loc] = If(std::num::NonZeroUsize::new(program_codes.len()));
+                            EndIf
+                        }),
+                        "{" => {
+                            blocks.push(Block { kind: BlockKind::Loop, loc: program_codes.len(), span });
+                            Some(Loop)
+                        }
                         "}" => {
-                            Some(EndLoop(
-                            if let Some(loc) = program_codes.iter().rposition(|x| matches!(x, Loop)) {
-                                loc
+                            // a loop can end inside if statements started in it as long as the innermost ends straight after, eg. `{ dup gez ( > })`
+                            let ifs = blocks.iter().rev().take_while(|block| block.kind == BlockKind::If).count();
+                            if ifs > 0 && blocks.iter().rev().nth(ifs).is_some_and(|block| block.kind == BlockKind::Loop)
+                                    && words.as_slice().first().is_some_and(|(next, ..)| next.is_word(")")) {
+                                Some(EndLoop(blocks.remove(blocks.len() - ifs - 1).loc))
                             } else {
-                                super::print_err("} without {");
-                                continue;
-                            }))}
+                                close!(BlockKind::Loop).map(EndLoop)
+                            }
+                        }
                         "+@" => Some(GtZero),
                         "@" => Some(EqZero),
                         "#+" => Some(HeapAlloc),
@@ -296,11 +556,40 @@ pub fn parse_program_code(chars: impl IntoIterator<Item = char>, interpreter: Pa
                         "*" => Some(Op(MUL)),
                         "/" => Some(Op(DIV)),
                         "%" => Some(Op(MOD)),
+                        "shl" => Some(Op(SHL)),
+                        "shr" => Some(Op(SHR)),
+                        "ushr" => Some(Op(USHR)),
+                        "abs" => Some(Op(ABS)),
+                        "min" => Some(Op(MIN)),
+                        "max" => Some(Op(MAX)),
+                        "pow" => Some(Op(POW)),
+                        "<?" => Some(Op(LT)),
+                        ">?" => Some(Op(GT)),
+                        "<=?" => Some(Op(LE)),
+                        ">=?" => Some(Op(GE)),
+                        "=?" => Some(Op(EQ)),
+                        "!=?" => Some(Op(NE)),
+                        "f~" => Some(FOp(FloatOpCode::NEG)),
+                        "f+" => Some(FOp(FloatOpCode::ADD)),
+                        "f-" => Some(FOp(FloatOpCode::SUB)),
+                        "f*" => Some(FOp(FloatOpCode::MUL)),
+                        "f/" => Some(FOp(FloatOpCode::DIV)),
+                        "f%" => Some(FOp(FloatOpCode::MOD)),
+                        "f+@" => Some(FGtZero),
+                        "f@" => Some(FEqZero),
+                        "itof" => Some(IntToFloat),
+                        "ftoi" => Some(FloatToInt),
                         _ => {
-                            if let Some(int) = word.parse::<i64>().ok() {
-                                Some(Int(int))
+                            if let Some(float) = parse_float(&word) {
+                                Some(Int(from_float(float)))
+                            } else if forward_calls.declared.contains(&word) || blocks.iter().any(|block| block.kind == BlockKind::Func) {
+                                // may be a function that is defined later, so it is called by name until it is
+                                forward_calls.calls.push((word.clone(), program_codes.len()));
+                                late_calls.push(word);
+                                Some(LateCall(late_calls.len() - 1))
                             } else {
-                                println!("!? {} ?", word);
+                                println!("!? {} ?", unmangle(&word));
+                                compiled = false;
                                 None
                             }
                         }
@@ -310,59 +599,256 @@ pub fn parse_program_code(chars: impl IntoIterator<Item = char>, interpreter: Pa
             }
         }
         if compiler_optimise {
+            // the codes optimised together are shown as compiled from the last word when expanding
             macro_rules! remove_last {
-                ($num:expr) => {
-                    program_codes.truncate(program_codes.len() - $num)
-                };
+                ($num:expr) => { {
+                    program_codes.truncate(program_codes.len() - $num);
+                    if let Some(last) = expansion.as_mut().and_then(|expansion| expansion.last_mut()) {
+                        last.codes.start = last.codes.start.min(program_codes.len());
+                    }
+                } };
             }
             macro_rules! replace_last {
                 ($num:expr, $slice:expr) => { {
-                    program_codes.truncate(program_codes.len() - $num);
+                    remove_last!($num);
                     program_codes.extend_from_slice($slice);
                 } };
             }
+            // rewrites that regroup or cancel arithmetic are only exact when it wraps or grows
+            let wrapping = matches!(overflow_mode, OverflowMode::Wrapping|OverflowMode::Big);
+            // folds to the result only if it would not print a warning or error at run time, or need to be big
+            let fold = |op: OpCode, left: Value, right: Value| match overflow_mode {
+                OverflowMode::Big => match apply_big_op(op, &Cell::Int(left), &Cell::Int(right)) {
+                    Ok(Cell::Int(value)) => Some(value),
+                    _ => None
+                },
+                _ => match apply_op(op, left, right, overflow_mode) {
+                    Ok((value, overflowed)) if !overflowed || overflow_mode == OverflowMode::Saturating => Some(value),
+                    _ => None
+                }
+            };
             loop {
-                match &program_codes[std::cmp::max(program_codes.len().saturating_sub(4), pc)..] {
-                    &[.., Int(_)|GtZero|EqZero|Dup|Swap|Op(_), Int(0), Dup] => replace_last!(3, &[Int(0), Dup]),
-                    &[.., Int(_)|PushMem|GtZero|EqZero, Int(drop), Dup] if drop < 0 => if drop == -1 {remove_last!(3)} else {replace_last!(3, &[Int(drop + 1), Dup])},
-                    &[.., Int(swap), Swap, Int(drop), Dup] if drop < 0 && -drop >= swap + 2 => replace_last!(4, &[Int(drop), Dup]),
-                    &[.., Int(num1), Swap, Int(num2), Swap] if num1 == num2 => remove_last!(4),
-                    &[.., Int(dupped), Dup, Int(num2), Dup] if dupped > 0 => if dupped + num2 == 0 { remove_last!(4) } else { replace_last!(4, &[Int(dupped + num2), Dup]) },
-                    &[.., Int(num), Int(0), Swap, op] if matches!(op, PopMemLoc|PushMem|PopMem|HeapAlloc) => replace_last!(4, &[op, Int(num)]),
-                    &[.., PushMem, Int(num2), Int(0), Swap] => replace_last!(4, &[Int(num2), PushMem]),
-                    &[.., Int(num1), Int(num2), Int(0), Swap] => replace_last!(4, &[Int(num2), Int(num1)]),
-                    &[.., Int(0), Op(SUB|ADD|OR)] => remove_last!(2),
-                    &[.., Int(1), Op(MUL|DIV)] => remove_last!(2),
-                    &[.., Int(-1), Op(AND)] => remove_last!(2),
-                    &[.., Int(num), Op(SUB)] => replace_last!(2, &[Int(-num), Op(ADD)]),
-                    &[.., Op(NEG), Op(op @ ADD|op @ SUB)] => replace_last!(2, &[Op(if op == ADD { SUB } else { ADD })]),
-                    &[.., Op(MUL), Int(num), Op(MUL)] => replace_last!(3, &[Int(num), Op(MUL), Op(MUL)]),
-                    &[.., Op(ADD), Int(num), Op(ADD)] => replace_last!(3, &[Int(num), Op(ADD), Op(ADD)]),
-                    &[.., Int(num1), Op(op1), Int(num2), Op(op2)] if num1 == num2 && matches!((op1, op2), (ADD, SUB)|(SUB, ADD)|(MUL, DIV)) => remove_last!(4),
-                    &[.., Op(NEG), Op(NEG)] => remove_last!(2),
-                    &[.., Op(NOT), Op(NOT)] => remove_last!(2),
-                    &[.., Int(num), EqZero] => replace_last!(2, &[Int(if num == 0 { -1 } else { 0 })]),
-                    &[.., Int(num), GtZero] => replace_last!(2, &[Int(if num > 0 { -1 } else { 0 })]),
-                    &[.., Int(num), Op(NEG)] => replace_last!(2, &[Int(-num)]),
-                    &[.., Int(num), Op(NOT)] => replace_last!(2, &[Int(!num)]),
-                    &[.., Int(left), Int(right), Op(op)] if !matches!(op, NEG|NOT) => {
-                        remove_last!(3);
-                        program_codes.push(Int(match op {
-                            AND => left & right,
-                            OR  => left | right,
-                            XOR => left ^ right,
-                            ADD => left.overflowing_add(right).0,
-                            SUB => left.overflowing_sub(right).0,
-                            MUL => left.overflowing_mul(right).0,
-                            DIV => left.overflowing_div(right).0,
-                            MOD => left % right,
-                            NOT|NEG => 0
-                        }));
+                match program_codes[std::cmp::max(program_codes.len().saturating_sub(4), pc)..] {
+                    [.., Int(_)|GtZero|EqZero|Dup|Swap|Op(_), Int(0), Dup] => replace_last!(3, &[Int(0), Dup]),
+                    [.., Int(_)|PushMem|GtZero|EqZero, Int(drop), Dup] if drop < 0 => if drop == -1 {remove_last!(3)} else {replace_last!(3, &[Int(drop + 1), Dup])},
+                    [.., Int(swap), Swap, Int(drop), Dup] if drop < 0 && -drop >= swap + 2 => replace_last!(4, &[Int(drop), Dup]),
+                    [.., Int(num1), Swap, Int(num2), Swap] if num1 == num2 => remove_last!(4),
+                    [.., Int(dupped), Dup, Int(num2), Dup] if dupped > 0 => if dupped + num2 == 0 { remove_last!(4) } else { replace_last!(4, &[Int(dupped + num2), Dup]) },
+                    [.., Int(num), Int(0), Swap, op] if matches!(op, PopMemLoc|PushMem|PopMem|HeapAlloc) => replace_last!(4, &[op, Int(num)]),
+                    [.., PushMem, Int(num2), Int(0), Swap] => replace_last!(4, &[Int(num2), PushMem]),
+                    [.., Int(num1), Int(num2), Int(0), Swap] => replace_last!(4, &[Int(num2), Int(num1)]),
+                    [.., Int(0), Op(SUB|ADD|OR)] => remove_last!(2),
+                    [.., Int(1), Op(MUL|DIV)] => remove_last!(2),
+                    [.., Int(-1), Op(AND)] => remove_last!(2),
+                    [.., Int(0), Op(SHL|SHR|USHR)] => remove_last!(2),
+                    [.., Int(1), Op(POW)] => remove_last!(2),
+                    [.., Op(ABS), Op(ABS)] => remove_last!(1),
+                    [.., Int(num), Op(SUB)] if wrapping && num != Value::MIN => replace_last!(2, &[Int(-num), Op(ADD)]),
+                    [.., Op(NEG), Op(op @ ADD|op @ SUB)] if wrapping => replace_last!(2, &[Op(if op == ADD { SUB } else { ADD })]),
+                    [.., Op(MUL), Int(num), Op(MUL)] if wrapping => replace_last!(3, &[Int(num), Op(MUL), Op(MUL)]),
+                    [.., Op(ADD), Int(num), Op(ADD)] if wrapping => replace_last!(3, &[Int(num), Op(ADD), Op(ADD)]),
+                    [.., Int(num1), Op(op1), Int(num2), Op(op2)] if wrapping && num1 == num2 && matches!((op1, op2), (ADD, SUB)|(SUB, ADD)) => remove_last!(4),
+                    [.., Op(NEG), Op(NEG)] if wrapping => remove_last!(2),
+                    [.., Op(NOT), Op(NOT)] => remove_last!(2),
+                    [.., Int(num), EqZero] => replace_last!(2, &[Int(if num == 0 { -1 } else { 0 })]),
+                    [.., Int(num), GtZero] => replace_last!(2, &[Int(if num > 0 { -1 } else { 0 })]),
+                    [.., Int(num), Op(op)] if op.is_unary() && fold(op, 0, num).is_some() => replace_last!(2, &[Int(fold(op, 0, num).unwrap_or_default())]),
+                    [.., Int(left), Int(right), Op(op)] if !op.is_unary() && fold(op, left, right).is_some() => {
+                        replace_last!(3, &[Int(fold(op, left, right).unwrap_or_default())]);
                     }
+                    [.., Int(num), FOp(FloatOpCode::NEG)] => replace_last!(2, &[Int(apply_float_op(FloatOpCode::NEG, 0, num))]),
+                    [.., Int(left), Int(right), FOp(op)] if op != FloatOpCode::NEG => replace_last!(3, &[Int(apply_float_op(op, left, right))]),
+                    [.., Int(num), FEqZero] => replace_last!(2, &[Int(if to_float(num) == 0.0 { -1 } else { 0 })]),
+                    [.., Int(num), FGtZero] => replace_last!(2, &[Int(if to_float(num) > 0.0 { -1 } else { 0 })]),
+                    [.., Int(num), IntToFloat] => replace_last!(2, &[Int(from_float(num as f64))]),
+                    [.., Int(num), FloatToInt] => replace_last!(2, &[Int(to_float(num) as Value)]),
                     _ => break
                 }
             }
         }
     }
+    if let Some(last) = expansion.as_mut().and_then(|expansion| expansion.last_mut()) {
+        last.codes.end = program_codes.len();
+    }
+    // calls to undeclared names are only waited for until the end of the function they are in, or of the file
+    if !forward_calls.in_file && !blocks.iter().any(|block| block.kind == BlockKind::Func) {
+        forward_calls.calls.retain(|(name, _)| {
+            let declared = forward_calls.declared.contains(name);
+            if !declared {
+                println!("!? {} ?", unmangle(name));
+            }
+            declared
+        });
+    }
+    compiled
 }
-//;
\ No newline at end of file
+//;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpret::Interpreter;
+
+    // the codes compiled from source, without optimising them unless the constants in it are
+    fn compile(interpreter: &mut Interpreter, source: &str) -> Vec<ProgramCode> {
+        let start = interpreter.program_len();
+        parse_program_code(source.chars(), interpreter.access_for_parsing(), false);
+        interpreter.access_for_parsing().program_codes[start..].to_vec()
+    }
+
+    // compiles source on its own, checking that it leaves no blocks open
+    fn compile_closed(source: &str) -> Vec<ProgramCode> {
+        let mut interpreter = Interpreter::new();
+        let codes = compile(&mut interpreter, source);
+        assert!(interpreter.blocks_mut().is_empty(), "{} leaves blocks open", source);
+        codes
+    }
+
+    fn at(loc: usize) -> Option<std::num::NonZeroUsize> {
+        std::num::NonZeroUsize::new(loc)
+    }
+
+    fn span(column: usize) -> Span {
+        Span { start: column - 1, end: column, line: 1, column }
+    }
+
+    #[test]
+    fn interleaved_blocks() {
+        use ProgramCode::*;
+        // the `(` is ended where the `}` is, then the code stops
+        assert_eq!(compile_closed("{ 1 ( }"), [Loop, Int(1), If(at(3)), EndIf, Err, EndLoop(0)]);
+        assert_eq!(block_err("}", span(7), Some(&Block { kind: BlockKind::If, loc: 2, span: span(5) })),
+            "`}` at line 1 column 7 cannot close `(` at line 1 column 5");
+        assert_eq!(block_err(")", span(1), None), "`)` at line 1 column 1 has nothing to close");
+    }
+
+    #[test]
+    fn loop_exits() {
+        use ProgramCode::*;
+        // a loop can end just before the if statement in it does
+        assert_eq!(compile_closed("{ 1 ( } )"), [Loop, Int(1), If(at(4)), EndLoop(0), EndIf]);
+        assert_eq!(compile_closed("{ 1 ( 2 ( } ) )"), [Loop, Int(1), If(at(7)), Int(2), If(at(6)), EndLoop(0), EndIf, EndIf]);
+    }
+
+    #[test]
+    fn else_not_followed_by_if() {
+        use ProgramCode::*;
+        assert_eq!(compile_closed("1 ( 2 )! ( 3 )"), [Int(1), If(at(3)), Int(2), ElseIf(at(4)), If(at(6)), Int(3), EndIf]);
+        // ended as if it were followed by `0 ( )`, then the code stops
+        assert_eq!(compile_closed("1 ( 2 )! 3 )"), [Int(1), If(at(3)), Int(2), ElseIf(at(6)), Int(3), Int(0), If(at(7)), EndIf, Err]);
+        assert_eq!(compile_closed("[f 1 ( )! ] 4"), [FuncStart(at(8)), Int(1), If(at(3)), ElseIf(at(5)), Int(0), If(at(6)), EndIf, Err, FuncEnd, Int(4)]);
+        assert_eq!(block_err(")", span(12), Some(&Block { kind: BlockKind::Else, loc: 3, span: span(7) })),
+            "`)` at line 1 column 12 cannot follow `)!` at line 1 column 7, which must be followed by a `(`");
+    }
+
+    fn words(source: &str) -> std::vec::IntoIter<Word> {
+        crate::lex::lex(source.chars()).into_iter().map(|token| (token.kind, token.span, 0)).collect::<Vec<Word>>().into_iter()
+    }
+
+    fn tokens(source: &str) -> Vec<TokenKind> {
+        words(source).map(|(token, ..)| token).collect()
+    }
+
+    fn expand(body: &str, params: &[&str], args: &str) -> Result<Vec<TokenKind>, String> {
+        let params: Vec<String> = params.iter().map(|param| param.to_string()).collect();
+        expand_macro("m", &tokens(body), &params, &mut words(args), 7)
+    }
+
+    // the tokens of words that may have been renamed, which cannot be lexed as they contain spaces
+    fn renamed(words: &[&str]) -> Vec<TokenKind> {
+        words.iter().map(|word| if word.contains(' ') { TokenKind::Word(word.to_string()) } else { tokens(word).remove(0) }).collect()
+    }
+
+    #[test]
+    fn macro_arguments() {
+        assert_eq!(expand("$a $b -", &["a", "b"], "1 2"), Ok(tokens("1 2 -")));
+        // the words after the arguments are left for after the expansion
+        let mut rest = words("1 2 3");
+        assert_eq!(expand_macro("m", &tokens("$a"), &["a".to_owned()], &mut rest, 7), Ok(tokens("1")));
+        assert_eq!(rest.map(|(token, ..)| token).collect::<Vec<TokenKind>>(), tokens("2 3"));
+        // a parameter in a word is replaced in it
+        assert_eq!(expand("*$v :$v;1 @$v", &["v"], "count"), Ok(tokens("*count :count;1 @count")));
+        // a parameter not in the macro's parameters is left alone
+        assert_eq!(expand("$b", &["a"], "1"), Ok(tokens("$b")));
+        assert_eq!(expand("$a $b", &["a", "b"], "1"), Result::Err("m expects 2 arguments but got 1".to_owned()));
+    }
+
+    #[test]
+    fn macro_groups() {
+        assert_eq!(expand("$body $body", &["body"], "$( 1 + $) 2"), Ok(tokens("1 + 1 +")));
+        assert_eq!(expand("$a", &["a"], "$( $( 1 $) $)"), Ok(tokens("$( 1 $)")));
+        assert_eq!(expand("$a", &["a"], "$( $)"), Ok(Vec::new()));
+        assert_eq!(expand("$a", &["a"], "$( 1"), Result::Err("unclosed $( in an argument to m".to_owned()));
+        assert_eq!(expand("*$a", &["a"], "$( x y $)"), Result::Err("the argument for $a in m must be a single word".to_owned()));
+    }
+
+    #[test]
+    fn macro_hygiene() {
+        // the names declared in the body are renamed wherever they are used in it, and nothing else is
+        assert_eq!(expand(":i 5 i . > *i [f|g i ] f g @f ;g h", &[], ""),
+            Ok(renamed(&[":i 7", "5", "i 7", ".", ">", "*i 7", "[f 7|g 7", "i 7", "]", "f 7", "g 7", "@f 7", ";g 7", "h"])));
+        assert_eq!(expand("[;inner x ] inner :c;1 c", &[], ""), Ok(renamed(&["[;inner 7", "x", "]", "inner 7", ":c 7;", "1", "c 7"])));
+        assert_eq!(unmangle(":i 7"), ":i");
+        assert_eq!(unmangle("[f 7|g 7"), "[f|g");
+    }
+
+    #[test]
+    fn macro_depth() {
+        let mut interpreter = Interpreter::new();
+        compile(&mut interpreter, "[;forever 1 forever ]");
+        let (expansion, codes) = interpreter.expand("forever 5", false);
+        // stopped at the limit, leaving out the rest of the expansion but not the words after it
+        assert_eq!(expansion.iter().map(|word| word.depth).max(), Some(MAX_MACRO_DEPTH));
+        assert_eq!(codes, [ProgramCode::Int(1); MAX_MACRO_DEPTH].into_iter().chain([ProgramCode::Int(5)]).collect::<Vec<ProgramCode>>());
+    }
+
+    #[test]
+    fn constants() {
+        use ProgramCode::*;
+        let mut interpreter = Interpreter::new();
+        assert_eq!(compile(&mut interpreter, ":area;3 4 *\narea area +"), [Int(12), Int(12), Op(OpCode::ADD)]);
+        assert_eq!(compile(&mut interpreter, ":s;\"ab\"\ns"), [Int(-1), Int(98), Int(97)]);
+        // the value ends at a `]` ending the function it is in
+        compile(&mut interpreter, "[f :k;7 ]");
+        assert_eq!(compile(&mut interpreter, "k"), [Int(7)]);
+    }
+
+    #[test]
+    fn constant_groups() {
+        use ProgramCode::*;
+        let mut interpreter = Interpreter::new();
+        assert_eq!(compile(&mut interpreter, ":x;$( 1\n2 + $) x 5"), [Int(3), Int(5)]);
+        assert_eq!(compile(&mut interpreter, "[f :y;$( 4 $) y ]")[1..3], [Int(4), FuncEnd]);
+        compile(&mut interpreter, ":z;$( 1");
+        assert!(!interpreter.is_defined("z"));
+    }
+
+    #[test]
+    fn invalid_constants() {
+        let mut interpreter = Interpreter::new();
+        // a word that does not compile is not left out of the value
+        compile(&mut interpreter, ":c;7 zz");
+        assert!(!interpreter.is_defined("c"));
+        compile(&mut interpreter, "[f :k;7 k ]");
+        assert!(!interpreter.is_defined("k"));
+        assert!(interpreter.is_defined("f"));
+        // reading memory cannot be done when it is declared
+        compile(&mut interpreter, ":m;1 . <");
+        assert!(!interpreter.is_defined("m"));
+        compile(&mut interpreter, ":e;");
+        assert!(!interpreter.is_defined("e"));
+    }
+
+    #[test]
+    fn constant_redefinition() {
+        use ProgramCode::*;
+        assert!(!redefines_constant(None, &[1], true));
+        assert!(!redefines_constant(Some(&Variable::Constant(vec![1])), &[1], true));
+        assert!(redefines_constant(Some(&Variable::Constant(vec![1])), &[2], true));
+        assert!(redefines_constant(Some(&Variable::Variable(3)), &[1], true));
+        // a name from another module is shadowed without a warning
+        assert!(!redefines_constant(Some(&Variable::Constant(vec![1])), &[2], false));
+        let mut interpreter = Interpreter::new();
+        assert_eq!(compile(&mut interpreter, ":x;1\n:x;2\nx"), [Int(2)]);
+    }
+}
\ No newline at end of file
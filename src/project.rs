@@ -0,0 +1,187 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use super::interpret::{DEFAULT_HEAP_START, DEFAULT_MEMORY_SIZE};
+
+// the name of the manifest file at the root of a project
+pub const MANIFEST: &str = "stack_sharp.toml";
+
+pub struct Project {
+    pub name: String,
+    // the directory containing the manifest, searched for imports
+    pub root: PathBuf,
+    // the file imported on start up, relative to the root
+    pub entry: Option<PathBuf>,
+    // the function called after importing the entry file, if it is defined
+    pub main: String,
+    // directories of local packages, searched for imports after the root
+    pub deps: Vec<PathBuf>,
+    pub memory_size: usize,
+    pub heap_start: usize,
+}
+
+//: manifest values
+enum ManifestValue {
+    Str(String),
+    Int(usize),
+    List(Vec<String>),
+}
+
+fn parse_string(value: &str) -> Option<String> {
+    value.strip_prefix('"')?.strip_suffix('"').map(str::to_owned)
+}
+
+fn parse_value(value: &str) -> Option<ManifestValue> {
+    if let Some(list) = value.strip_prefix('[').and_then(|value| value.strip_suffix(']')) {
+        let items: Option<Vec<String>> = list.split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(parse_string)
+            .collect();
+        items.map(ManifestValue::List)
+    } else if let Some(string) = parse_string(value) {
+        Some(ManifestValue::Str(string))
+    } else {
+        value.replace('_', "").parse::<usize>().ok().map(ManifestValue::Int)
+    }
+}
+
+// removes a comment from the end of a line, ignoring `#`s in strings
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, chr) in line.char_indices() {
+        match chr {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+//;
+
+impl Project {
+    // parses a manifest made of `key = value` lines, where each value is a "string", an integer or a ["list", "of", "strings"]
+    pub fn parse(source: &str, root: PathBuf) -> Result<Project, String> {
+        let mut project = Project {
+            name: root.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            root,
+            entry: None,
+            main: "main".to_owned(),
+            deps: Vec::new(),
+            memory_size: DEFAULT_MEMORY_SIZE,
+            heap_start: DEFAULT_HEAP_START,
+        };
+        let mut heap_start: Option<usize> = None;
+        for (line_num, line) in source.lines().enumerate().map(|(i, line)| (i + 1, strip_comment(line).trim())) {
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected `key = value`", line_num));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let value = parse_value(value).ok_or_else(|| format!("line {}: invalid value {}", line_num, value))?;
+            match (key, value) {
+                ("name", ManifestValue::Str(name)) => project.name = name,
+                ("entry", ManifestValue::Str(entry)) => project.entry = Some(PathBuf::from(entry)),
+                ("main", ManifestValue::Str(main)) => project.main = main,
+                ("deps", ManifestValue::List(deps)) => project.deps = deps.iter().map(|dep| project.root.join(dep)).collect(),
+                ("memory_size", ManifestValue::Int(size)) => project.memory_size = size,
+                ("heap_start", ManifestValue::Int(start)) => heap_start = Some(start),
+                ("name"|"entry"|"main", _) => return Err(format!("line {}: {} must be a string", line_num, key)),
+                ("deps", _) => return Err(format!("line {}: deps must be a list of strings", line_num)),
+                ("memory_size"|"heap_start", _) => return Err(format!("line {}: {} must be an integer", line_num, key)),
+                _ => return Err(format!("line {}: unknown key {}", line_num, key)),
+            }
+        }
+        // address 0 is for input and output, so there must be room for at least one variable or heap cell after it
+        if project.memory_size < 2 {
+            return Err(format!("memory_size must be at least 2, not {}", project.memory_size));
+        }
+        // the heap starts halfway through memory unless told otherwise
+        project.heap_start = heap_start.unwrap_or(project.memory_size / 2);
+        if project.heap_start < 1 || project.heap_start >= project.memory_size {
+            return Err(format!("heap_start must be between 1 and memory_size - 1 ({})", project.memory_size - 1));
+        }
+        Ok(project)
+    }
+
+    // loads the manifest at a path, or in it if it is a directory
+    pub fn load(path: &Path) -> Result<Project, String> {
+        let manifest = if path.is_dir() { path.join(MANIFEST) } else { path.to_path_buf() };
+        let source = fs::read_to_string(&manifest).map_err(|err| format!("failed to read {}: {}", manifest.display(), err))?;
+        let root = fs::canonicalize(&manifest).ok()
+            .and_then(|manifest| manifest.parent().map(Path::to_path_buf))
+            .unwrap_or_default();
+        Project::parse(&source, root).map_err(|err| format!("invalid {}: {}", manifest.display(), err))
+    }
+
+    // loads the manifest in the current directory, if there is one
+    pub fn find() -> Option<Result<Project, String>> {
+        let manifest = Path::new(MANIFEST);
+        if manifest.is_file() {
+            Some(Project::load(manifest))
+        } else {
+            None
+        }
+    }
+
+    // the project's directories to search for imports, in order
+    pub fn search_paths(&self) -> Vec<PathBuf> {
+        std::iter::once(self.root.clone()).chain(self.deps.iter().cloned()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Project, String> {
+        Project::parse(source, PathBuf::from("/projects/demo"))
+    }
+
+    #[test]
+    fn manifests() {
+        let project = parse("name = \"fibs\"  # the name\n\n# a comment\nentry = \"src/main.ss\"\nmain = \"start\"\ndeps = [\"../lib\", \"vendor/#1\",]\nmemory_size = 2_000\nheap_start = 1500").unwrap();
+        assert_eq!(project.name, "fibs");
+        assert_eq!(project.entry, Some(PathBuf::from("src/main.ss")));
+        assert_eq!(project.main, "start");
+        assert_eq!(project.deps, [PathBuf::from("/projects/demo/../lib"), PathBuf::from("/projects/demo/vendor/#1")]);
+        assert_eq!((project.memory_size, project.heap_start), (2000, 1500));
+        assert_eq!(project.search_paths()[0], PathBuf::from("/projects/demo"));
+    }
+
+    #[test]
+    fn defaults() {
+        let project = parse("").unwrap();
+        assert_eq!(project.name, "demo");
+        assert_eq!(project.entry, None);
+        assert_eq!(project.main, "main");
+        assert!(project.deps.is_empty());
+        assert_eq!((project.memory_size, project.heap_start), (DEFAULT_MEMORY_SIZE, DEFAULT_HEAP_START));
+        // the heap starts halfway through memory
+        assert_eq!(parse("memory_size = 300").unwrap().heap_start, 150);
+        assert_eq!(parse("deps = []").unwrap().deps, Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn heap_start() {
+        assert_eq!(parse("memory_size = 10\nheap_start = 9").unwrap().heap_start, 9);
+        assert_eq!(parse("memory_size = 10\nheap_start = 10").err().unwrap(), "heap_start must be between 1 and memory_size - 1 (9)");
+        assert_eq!(parse("heap_start = 0").err().unwrap(), "heap_start must be between 1 and memory_size - 1 (999)");
+        assert_eq!(parse("memory_size = 1").err().unwrap(), "memory_size must be at least 2, not 1");
+        assert_eq!(parse("memory_size = 2").unwrap().heap_start, 1);
+    }
+
+    #[test]
+    fn invalid_manifests() {
+        assert_eq!(parse("colour = \"red\"").err().unwrap(), "line 1: unknown key colour");
+        assert_eq!(parse("\nname").err().unwrap(), "line 2: expected `key = value`");
+        assert_eq!(parse("name = fibs").err().unwrap(), "line 1: invalid value fibs");
+        assert_eq!(parse("name = 3").err().unwrap(), "line 1: name must be a string");
+        assert_eq!(parse("deps = \"lib\"").err().unwrap(), "line 1: deps must be a list of strings");
+        assert_eq!(parse("deps = [lib]").err().unwrap(), "line 1: invalid value [lib]");
+        assert_eq!(parse("memory_size = -5").err().unwrap(), "line 1: invalid value -5");
+        assert_eq!(parse("heap_start = \"1\"").err().unwrap(), "line 1: heap_start must be an integer");
+    }
+}
\ No newline at end of file
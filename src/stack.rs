@@ -8,6 +8,10 @@ impl<T> Stack<T> {
         Stack { stack: Vec::new() }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
     pub fn clear(&mut self) {
         self.stack.clear();
     }
@@ -22,9 +26,9 @@ impl<T> Stack<T> {
         self.stack.extend(values);
     }
 
-    pub fn try_peek(&self, from_top: usize) -> Option<T> where T: Copy {
+    pub fn try_peek(&self, from_top: usize) -> Option<T> where T: Clone {
         if from_top < self.stack.len() {
-            Some(self.stack[self.stack.len() - from_top - 1])
+            Some(self.stack[self.stack.len() - from_top - 1].clone())
         } else {
             super::print_err("stack underflow");
             None
@@ -76,14 +80,9 @@ impl<T> Stack<T> {
 impl<T> std::fmt::Display for Stack<T> where T: std::fmt::Display {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         for int in &self.stack {
-            if let Err(error) = write!(f, "{} ", int) {
-                return Err(error);
-            }
-        }
-        if let Err(error) = write!(f, "<") {
-            return Err(error);
+            write!(f, "{} ", int)?;
         }
-        Ok(())
+        write!(f, "<")
     }
 }
 //;
\ No newline at end of file